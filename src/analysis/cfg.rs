@@ -0,0 +1,541 @@
+//! Control-flow graph over the instructions of an assembled program.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::assembler::Program;
+use crate::ast::{Node, NodeValue};
+use crate::simulator::sign_extend;
+
+#[derive(Debug)]
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+    /// Maps every instruction address to the index of the block containing it.
+    pub block_of: BTreeMap<u16, usize>,
+    /// Block at the start of the first section, where the simulator starts executing.
+    pub entry: Option<usize>,
+    /// Blocks that are the target of a `JSR`, or of a `JSRR` whose target is known.
+    pub subroutines: BTreeSet<usize>,
+}
+
+/// Instructions that always execute one after another.
+#[derive(Debug)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub successors: Vec<Edge>,
+    /// Blocks with an edge into this one, other than `JSR` calls.
+    pub predecessors: Vec<usize>,
+    pub exit: Exit,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub address: u16,
+    pub word: u16,
+    /// Index of the node the instruction was assembled from.
+    pub node: usize,
+}
+
+/// Memory an `LD`, `ST`, `LDR` or `STR` accesses, as far as it can be told from the instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    Address(u16),
+    /// Offset from the address in the base register.
+    Offset(usize, u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub kind: EdgeKind,
+    pub target: Target,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Execution continues at the next address.
+    FallThrough,
+    /// A conditional `BR` is taken.
+    Branch,
+    /// An unconditional `BR`, or a `JMP` whose target is known.
+    Jump,
+    /// A `JSR`, or a `JSRR` whose target is known, enters a subroutine.
+    Call,
+    /// Execution continues after a `JSR` or `JSRR` returns.
+    CallReturn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Block(usize),
+    /// Address holding data assembled by the program, e.g. a `.FILL`.
+    Data(u16),
+    /// Address the program does not assemble anything at.
+    Outside(u16),
+}
+
+/// How execution leaves the last instruction of a block, other than through its edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// Only through the edges.
+    Edges,
+    Return,
+    InterruptReturn,
+    Halt,
+    /// `JMP` to the address in the register, which is not known.
+    IndirectJump(usize),
+}
+
+impl Block {
+    pub fn start(&self) -> u16 {
+        self.instructions[0].address
+    }
+
+    pub fn last(&self) -> &Instruction {
+        self.instructions.last().unwrap()
+    }
+}
+
+impl Instruction {
+    /// Registers the instruction reads, with the index of the operand that names each one.
+    /// `AND Rx, Rx, #0` only clears the register, so it does not count as reading it.
+    pub fn reads(&self) -> Vec<(usize, Option<usize>)> {
+        let word = self.word;
+        let dr = ((word >> 9) & 0x7) as usize;
+        let sr1 = ((word >> 6) & 0x7) as usize;
+        let sr2 = (word & 0x7) as usize;
+
+        match word >> 12 {
+            // ADD, AND
+            0b0001 | 0b0101 if word & 0x20 == 0 => vec![(sr1, Some(1)), (sr2, Some(2))],
+            0b0101 if word & 0x1F == 0 => Vec::new(),
+            0b0001 | 0b0101 => vec![(sr1, Some(1))],
+            // NOT, LDR
+            0b1001 | 0b0110 => vec![(sr1, Some(1))],
+            // ST, STI
+            0b0011 | 0b1011 => vec![(dr, Some(0))],
+            // STR
+            0b0111 => vec![(dr, Some(0)), (sr1, Some(1))],
+            // RET
+            0b1100 if sr1 == 7 => vec![(7, None)],
+            // JMP, JSRR
+            0b1100 => vec![(sr1, Some(0))],
+            0b0100 if word & 0x800 == 0 => vec![(sr1, Some(0))],
+            // OUT, PUTS, PUTSP
+            0b1111 if matches!(word & 0xFF, 0x21 | 0x22 | 0x24) => vec![(0, None)],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Register whose value the instruction sets the condition codes from.
+    pub fn condition_register(&self) -> Option<usize> {
+        match self.word >> 12 {
+            // ADD, AND, NOT, LD, LDI, LDR, LEA
+            0b0001 | 0b0101 | 0b1001 | 0b0010 | 0b1010 | 0b0110 | 0b1110 => {
+                Some(((self.word >> 9) & 0x7) as usize)
+            }
+            _ => None,
+        }
+    }
+
+    /// Register an `LD` or `LDR` loads and the slot it loads from.
+    pub fn load_slot(&self) -> Option<(usize, Slot)> {
+        match self.word >> 12 {
+            0b0010 | 0b0110 => self.slot(),
+            _ => None,
+        }
+    }
+
+    /// Register an `ST` or `STR` stores and the slot it stores to.
+    pub fn store_slot(&self) -> Option<(usize, Slot)> {
+        match self.word >> 12 {
+            0b0011 | 0b0111 => self.slot(),
+            _ => None,
+        }
+    }
+
+    fn slot(&self) -> Option<(usize, Slot)> {
+        let register = ((self.word >> 9) & 0x7) as usize;
+        match self.word >> 12 {
+            0b0010 | 0b0011 => Some((register, Slot::Address(self.pc_relative_target()))),
+            0b0110 | 0b0111 => {
+                let base_r = ((self.word >> 6) & 0x7) as usize;
+                Some((register, Slot::Offset(base_r, sign_extend(self.word, 6))))
+            }
+            _ => None,
+        }
+    }
+
+    /// Address a PC-relative instruction such as `LD` or `LEA` refers to.
+    pub fn pc_relative_target(&self) -> u16 {
+        (self.address.wrapping_add(1)).wrapping_add(sign_extend(self.word, 9))
+    }
+
+    /// Registers the instruction writes, including R7 for calls and traps.
+    pub fn writes(&self) -> Vec<usize> {
+        let word = self.word;
+        let dr = ((word >> 9) & 0x7) as usize;
+
+        match word >> 12 {
+            // ADD, AND, NOT, LD, LDI, LDR, LEA
+            0b0001 | 0b0101 | 0b1001 | 0b0010 | 0b1010 | 0b0110 | 0b1110 => vec![dr],
+            // JSR, JSRR
+            0b0100 => vec![7],
+            // GETC, IN
+            0b1111 if matches!(word & 0xFF, 0x20 | 0x23) => vec![0, 7],
+            0b1111 => vec![7],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Cfg {
+    pub fn build(program: &Program, nodes: &[Node]) -> Self {
+        Self::build_with_jumps(program, nodes, &BTreeMap::new())
+    }
+
+    /// Builds the graph knowing where the `JMP` and `JSRR` instructions at some addresses go,
+    /// e.g. from constant propagation.
+    pub fn build_with_jumps(program: &Program, nodes: &[Node], jumps: &BTreeMap<u16, u16>) -> Self {
+        let instructions = program
+            .source_map
+            .iter()
+            .filter(|(_, &node)| matches!(nodes[node].value, NodeValue::Instruction(_)))
+            .filter_map(|(&address, &node)| {
+                let word = program.word_at(address)?;
+                Some((
+                    address,
+                    Instruction {
+                        address,
+                        word,
+                        node,
+                    },
+                ))
+            })
+            .collect::<BTreeMap<u16, Instruction>>();
+
+        let mut leaders = BTreeSet::new();
+        for (&address, instruction) in &instructions {
+            let previous = address.wrapping_sub(1);
+            if !instructions.contains_key(&previous) || ends_block(instructions[&previous].word) {
+                leaders.insert(address);
+            }
+            if let Some(target) = static_target(instruction).or(jumps.get(&address).copied()) {
+                if instructions.contains_key(&target) {
+                    leaders.insert(target);
+                }
+            }
+        }
+
+        let mut blocks = Vec::<Block>::new();
+        let mut block_of = BTreeMap::new();
+        for (&address, &instruction) in &instructions {
+            if leaders.contains(&address) {
+                blocks.push(Block {
+                    instructions: Vec::new(),
+                    successors: Vec::new(),
+                    predecessors: Vec::new(),
+                    exit: Exit::Edges,
+                });
+            }
+            block_of.insert(address, blocks.len() - 1);
+            blocks.last_mut().unwrap().instructions.push(instruction);
+        }
+
+        let target = |address: u16| match block_of.get(&address) {
+            Some(&block) => Target::Block(block),
+            None if program.source_map.contains_key(&address) => Target::Data(address),
+            None => Target::Outside(address),
+        };
+
+        let mut subroutines = BTreeSet::new();
+        for block in &mut blocks {
+            let last = *block.last();
+            let next = last.address.wrapping_add(1);
+            let edge = |kind, address| Edge {
+                kind,
+                target: target(address),
+            };
+
+            let (successors, exit) = match last.word >> 12 {
+                // BR
+                0b0000 => {
+                    let successors = match (static_target(&last), (last.word >> 9) & 0x7) {
+                        (Some(branch), 0x7) => vec![edge(EdgeKind::Jump, branch)],
+                        (Some(branch), _) => vec![
+                            edge(EdgeKind::Branch, branch),
+                            edge(EdgeKind::FallThrough, next),
+                        ],
+                        (None, _) => vec![edge(EdgeKind::FallThrough, next)],
+                    };
+                    (successors, Exit::Edges)
+                }
+                // JMP, RET
+                0b1100 => match ((last.word >> 6) & 0x7, jumps.get(&last.address)) {
+                    (7, _) => (Vec::new(), Exit::Return),
+                    (_, Some(&jump)) => (vec![edge(EdgeKind::Jump, jump)], Exit::Edges),
+                    (base_r, None) => (Vec::new(), Exit::IndirectJump(base_r as usize)),
+                },
+                // RTI
+                0b1000 => (Vec::new(), Exit::InterruptReturn),
+                // JSR, JSRR
+                0b0100 => match static_target(&last).or(jumps.get(&last.address).copied()) {
+                    Some(callee) => {
+                        let call = edge(EdgeKind::Call, callee);
+                        if let Target::Block(subroutine) = call.target {
+                            subroutines.insert(subroutine);
+                        }
+                        (vec![call, edge(EdgeKind::CallReturn, next)], Exit::Edges)
+                    }
+                    None => (vec![edge(EdgeKind::CallReturn, next)], Exit::Edges),
+                },
+                // HALT
+                0b1111 if last.word & 0xFF == 0x25 => (Vec::new(), Exit::Halt),
+                _ => (vec![edge(EdgeKind::FallThrough, next)], Exit::Edges),
+            };
+
+            block.successors = successors;
+            block.exit = exit;
+        }
+
+        for index in 0..blocks.len() {
+            for edge in blocks[index].successors.clone() {
+                let Target::Block(successor) = edge.target else {
+                    continue;
+                };
+                if edge.kind != EdgeKind::Call && !blocks[successor].predecessors.contains(&index) {
+                    blocks[successor].predecessors.push(index);
+                }
+            }
+        }
+
+        let entry = program
+            .segments
+            .first()
+            .and_then(|segment| block_of.get(&segment.origin).copied());
+
+        Cfg {
+            blocks,
+            block_of,
+            entry,
+            subroutines,
+        }
+    }
+
+    /// Every instruction in address order.
+    pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.blocks.iter().flat_map(|block| &block.instructions)
+    }
+
+    pub fn instruction_at(&self, address: u16) -> Option<&Instruction> {
+        let block = &self.blocks[*self.block_of.get(&address)?];
+        let offset = address.wrapping_sub(block.start()) as usize;
+        block.instructions.get(offset)
+    }
+
+    /// Subroutine called by the `JSR` or `JSRR` at the end of the block, if it is known.
+    pub fn callee(&self, block: usize) -> Option<usize> {
+        self.blocks[block]
+            .successors
+            .iter()
+            .find(|edge| edge.kind == EdgeKind::Call)
+            .and_then(|edge| match edge.target {
+                Target::Block(callee) => Some(callee),
+                _ => None,
+            })
+    }
+
+    /// Blocks that execution can move to from the block without leaving the subroutine, i.e.
+    /// following every edge except `JSR` calls.
+    pub fn local_successors(&self, block: usize) -> impl Iterator<Item = usize> + '_ {
+        self.blocks[block]
+            .successors
+            .iter()
+            .filter(|edge| edge.kind != EdgeKind::Call)
+            .filter_map(|edge| match edge.target {
+                Target::Block(successor) => Some(successor),
+                _ => None,
+            })
+    }
+
+    /// Blocks reachable from the entry blocks. Calls are followed when `follow_calls` is set.
+    pub fn reachable(&self, entries: &[usize], follow_calls: bool) -> Vec<bool> {
+        let mut reached = vec![false; self.blocks.len()];
+        let mut pending = entries.to_vec();
+
+        while let Some(block) = pending.pop() {
+            if std::mem::replace(&mut reached[block], true) {
+                continue;
+            }
+
+            for edge in &self.blocks[block].successors {
+                if let Target::Block(successor) = edge.target {
+                    if follow_calls || edge.kind != EdgeKind::Call {
+                        pending.push(successor);
+                    }
+                }
+            }
+        }
+
+        reached
+    }
+}
+
+/// Whether the instruction transfers control, so the next address starts a new block.
+fn ends_block(word: u16) -> bool {
+    match word >> 12 {
+        // BR with at least one flag
+        0b0000 => word & 0x0E00 != 0,
+        // JMP, RET, RTI, JSR, JSRR
+        0b1100 | 0b1000 | 0b0100 => true,
+        // HALT
+        0b1111 => word & 0xFF == 0x25,
+        _ => false,
+    }
+}
+
+/// Address a `BR` or `JSR` transfers control to.
+fn static_target(instruction: &Instruction) -> Option<u16> {
+    let word = instruction.word;
+    let next = instruction.address.wrapping_add(1);
+    match word >> 12 {
+        0b0000 if word & 0x0E00 != 0 => Some(next.wrapping_add(sign_extend(word, 9))),
+        0b0100 if word & 0x800 != 0 => Some(next.wrapping_add(sign_extend(word, 11))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+
+    fn build(source: &str, jumps: &[(u16, u16)]) -> Cfg {
+        let (program, nodes) = assembler::assemble_source(source).unwrap();
+        Cfg::build_with_jumps(&program, &nodes, &jumps.iter().copied().collect())
+    }
+
+    fn edge(kind: EdgeKind, target: Target) -> Edge {
+        Edge { kind, target }
+    }
+
+    #[test]
+    fn splits_blocks_at_control_flow() {
+        let cfg = build(
+            "
+            .ORIG x3000
+                    AND R0, R0, #0
+            LOOP    ADD R0, R0, #1
+                    BRn LOOP
+                    JSR SUB
+                    HALT
+            SUB     RET
+            .END
+            ",
+            &[],
+        );
+
+        let starts = cfg.blocks.iter().map(Block::start).collect::<Vec<u16>>();
+        assert_eq!(starts, [0x3000, 0x3001, 0x3003, 0x3004, 0x3005]);
+        assert_eq!(cfg.entry, Some(0));
+        assert_eq!(
+            cfg.blocks[1].successors,
+            [
+                edge(EdgeKind::Branch, Target::Block(1)),
+                edge(EdgeKind::FallThrough, Target::Block(2)),
+            ]
+        );
+        assert_eq!(cfg.blocks[1].predecessors, [0, 1]);
+        assert_eq!(
+            cfg.blocks[2].successors,
+            [
+                edge(EdgeKind::Call, Target::Block(4)),
+                edge(EdgeKind::CallReturn, Target::Block(3)),
+            ]
+        );
+        assert_eq!(cfg.callee(2), Some(4));
+        assert_eq!(cfg.subroutines, BTreeSet::from([4]));
+        assert_eq!(cfg.blocks[3].exit, Exit::Halt);
+        assert_eq!(cfg.blocks[4].exit, Exit::Return);
+        assert_eq!(cfg.local_successors(2).collect::<Vec<usize>>(), [3]);
+        assert_eq!(cfg.reachable(&[0], false), [true, true, true, true, false]);
+        assert_eq!(cfg.reachable(&[0], true), [true; 5]);
+    }
+
+    #[test]
+    fn edges_to_data_and_outside_the_program() {
+        let cfg = build(
+            "
+            .ORIG x3000
+                    BRz DATA
+                    BRp #10
+                    HALT
+            DATA    .FILL #0
+            .END
+            ",
+            &[],
+        );
+
+        assert_eq!(
+            cfg.blocks[0].successors,
+            [
+                edge(EdgeKind::Branch, Target::Data(0x3003)),
+                edge(EdgeKind::FallThrough, Target::Block(1)),
+            ]
+        );
+        assert_eq!(
+            cfg.blocks[1].successors[0],
+            edge(EdgeKind::Branch, Target::Outside(0x300C))
+        );
+        assert_eq!(cfg.instruction_at(0x3003).map(|i| i.address), None);
+    }
+
+    #[test]
+    fn follows_known_jump_targets() {
+        let source = "
+            .ORIG x3000
+                    JMP R2
+                    JSRR R3
+                    HALT
+            TARGET  ADD R0, R0, #1
+                    RET
+            .END
+        ";
+
+        let cfg = build(source, &[]);
+        assert_eq!(cfg.blocks[0].successors, []);
+        assert_eq!(cfg.blocks[0].exit, Exit::IndirectJump(2));
+        assert_eq!(cfg.callee(1), None);
+        assert!(cfg.subroutines.is_empty());
+
+        let cfg = build(source, &[(0x3000, 0x3003), (0x3001, 0x3003)]);
+        let target = cfg.block_of[&0x3003];
+        assert_eq!(
+            cfg.blocks[0].successors,
+            [edge(EdgeKind::Jump, Target::Block(target))]
+        );
+        assert_eq!(cfg.blocks[0].exit, Exit::Edges);
+        assert_eq!(cfg.callee(1), Some(target));
+        assert_eq!(cfg.subroutines, BTreeSet::from([target]));
+    }
+
+    #[test]
+    fn finds_load_and_store_slots() {
+        let cfg = build(
+            ".ORIG x3000\nST R7, SAVE\nLDR R1, R6, #-1\nSTR R2, R6, #0\nHALT\nSAVE .BLKW #1\n.END",
+            &[],
+        );
+        let slots = cfg
+            .instructions()
+            .map(|i| (i.store_slot(), i.load_slot()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            slots,
+            [
+                (Some((7, Slot::Address(0x3004))), None),
+                (None, Some((1, Slot::Offset(6, 0xFFFF)))),
+                (Some((2, Slot::Offset(6, 0))), None),
+                (None, None),
+            ]
+        );
+    }
+}
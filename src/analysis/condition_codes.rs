@@ -0,0 +1,278 @@
+//! Warnings for `BR` instructions that test condition codes the author probably did not mean to.
+//!
+//! Only `ADD`, `AND`, `NOT`, `LD`, `LDI`, `LDR` and `LEA` set the condition codes, so a `BR`
+//! after a store still tests whatever was loaded or computed before it, and a `BR` after a call
+//! tests whatever the subroutine or trap routine happened to do last. A `BR` that tests condition
+//! codes set several instructions earlier is also reported, since an instruction added in between
+//! later could silently change what it tests.
+
+use std::collections::BTreeMap;
+
+use super::cfg::{Block, Cfg, EdgeKind, Instruction, Target};
+use super::mnemonic;
+use crate::ast::{Node, NodeError};
+
+/// Instructions other than `BR` that can run between setting the condition codes and testing them
+/// before it is reported.
+const FAR_AWAY: usize = 3;
+
+/// Where the condition codes at a point were last set, mapped to the fewest instructions other than
+/// `BR` that have run since, up to one more than `FAR_AWAY`.
+type Sources = BTreeMap<Source, usize>;
+
+/// Where the condition codes at a point were last set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Source {
+    /// Nothing has set them since the program started.
+    Nothing,
+    /// Set from the register by the instruction at the address.
+    Instruction(u16, usize),
+    /// Left by whatever the call at the address ran.
+    Call(u16),
+}
+
+pub fn check(cfg: &Cfg, nodes: &mut [Node]) {
+    for instruction in cfg.instructions() {
+        if instruction.word >> 12 == 0b0000 && instruction.word & 0x0E00 == 0 {
+            nodes[instruction.node].errors.push(NodeError::Warning(
+                "`BR` without `n`, `z` or `p` never branches; use `BRnzp` to always branch"
+                    .to_owned(),
+            ));
+        }
+    }
+
+    let Some(entry) = cfg.entry else {
+        return;
+    };
+
+    // Sources only get added and distances only shrink, so this settles
+    let mut sources = vec![None::<Sources>; cfg.blocks.len()];
+    sources[entry] = Some(Sources::from([(Source::Nothing, 0)]));
+    let mut pending = vec![entry];
+
+    while let Some(block) = pending.pop() {
+        let state = sources_after(&cfg.blocks[block], sources[block].clone().unwrap());
+
+        for edge in &cfg.blocks[block].successors {
+            let Target::Block(successor) = edge.target else {
+                continue;
+            };
+
+            let state = match edge.kind {
+                EdgeKind::CallReturn => {
+                    Sources::from([(Source::Call(cfg.blocks[block].last().address), 0)])
+                }
+                _ => state.clone(),
+            };
+
+            let existing = sources[successor].get_or_insert_with(Sources::new);
+            let mut changed = false;
+            for (source, distance) in state {
+                let existing = existing.entry(source).or_insert(usize::MAX);
+                if distance < *existing {
+                    *existing = distance;
+                    changed = true;
+                }
+            }
+            if changed {
+                pending.push(successor);
+            }
+        }
+    }
+
+    for (block, state) in cfg.blocks.iter().zip(sources) {
+        let Some(mut state) = state else {
+            continue;
+        };
+
+        for (i, instruction) in block.instructions.iter().enumerate() {
+            let flags = (instruction.word >> 9) & 0x7;
+            if instruction.word >> 12 == 0b0000 && flags != 0 && flags != 0x7 {
+                let previous = i.checked_sub(1).map(|i| &block.instructions[i]);
+                if let Some(problem) = describe_problem(cfg, &state, previous, nodes) {
+                    nodes[instruction.node]
+                        .errors
+                        .push(NodeError::Warning(format!(
+                            "`{}` {}",
+                            mnemonic(instruction),
+                            problem
+                        )));
+                }
+            }
+
+            state = sources_after_instruction(instruction, state);
+        }
+    }
+}
+
+/// Describes what is likely wrong with the condition codes a `BR` tests, if anything.
+fn describe_problem(
+    cfg: &Cfg,
+    sources: &Sources,
+    previous: Option<&Instruction>,
+    nodes: &[Node],
+) -> Option<String> {
+    let describe = |address: u16| {
+        let instruction = cfg.instruction_at(address).unwrap();
+        format!(
+            "`{}` on line {}",
+            mnemonic(instruction),
+            nodes[instruction.node].start_loc.line
+        )
+    };
+
+    if sources.contains_key(&Source::Nothing) {
+        return Some("tests condition codes that nothing has set on some paths".to_owned());
+    }
+
+    let call = sources.keys().find_map(|source| match *source {
+        Source::Call(address) => Some(address),
+        _ => None,
+    });
+    if let Some(call) = call {
+        return Some(format!(
+            "tests condition codes left by the {}; set them from the result first, e.g. with \
+             `ADD R0, R0, #0`",
+            describe(call)
+        ));
+    }
+
+    // Only instructions are left, and the nearest one is what the author most likely meant
+    let (address, register, distance) = (sources.iter())
+        .filter_map(|(source, &distance)| match *source {
+            Source::Instruction(address, register) => Some((address, register, distance)),
+            _ => None,
+        })
+        .min_by_key(|&(_, _, distance)| distance)?;
+
+    // A store right before the branch suggests the author expected it to set the condition codes
+    if let Some(stored) = previous.and_then(stored_register) {
+        let set_from_stored = (sources.keys()).any(
+            |source| matches!(*source, Source::Instruction(_, register) if register == stored),
+        );
+        if !set_from_stored {
+            return Some(format!(
+                "follows a store, which does not set condition codes, so it tests `R{}` from the \
+                 {} rather than `R{}`",
+                register,
+                describe(address),
+                stored
+            ));
+        }
+    }
+
+    (distance > FAR_AWAY).then(|| {
+        format!(
+            "tests condition codes set from `R{}` by the {}, more than {} instructions earlier; \
+             set them again right before the branch",
+            register,
+            describe(address),
+            FAR_AWAY
+        )
+    })
+}
+
+/// Register an `ST`, `STI` or `STR` stores.
+fn stored_register(instruction: &Instruction) -> Option<usize> {
+    match instruction.word >> 12 {
+        0b0011 | 0b1011 | 0b0111 => Some(((instruction.word >> 9) & 0x7) as usize),
+        _ => None,
+    }
+}
+
+fn sources_after(block: &Block, state: Sources) -> Sources {
+    (block.instructions.iter()).fold(state, |state, instruction| {
+        sources_after_instruction(instruction, state)
+    })
+}
+
+fn sources_after_instruction(instruction: &Instruction, mut state: Sources) -> Sources {
+    if let Some(register) = instruction.condition_register() {
+        return Sources::from([(Source::Instruction(instruction.address, register), 0)]);
+    }
+
+    match instruction.word >> 12 {
+        // BR
+        0b0000 => {}
+        // TRAP
+        0b1111 => return Sources::from([(Source::Call(instruction.address), 0)]),
+        _ => {
+            for distance in state.values_mut() {
+                *distance = (*distance + 1).min(FAR_AWAY + 1);
+            }
+        }
+    }
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::warnings;
+
+    fn program(body: &str) -> String {
+        format!(
+            ".ORIG x3000\n{}\nDONE HALT\nSUB AND R1, R1, #0\nRET\nVALUE .FILL #5\nSAVED .BLKW #1\n.END",
+            body
+        )
+    }
+
+    #[test]
+    fn branches_after_setting_condition_codes() {
+        let source = program("ADD R0, R0, #0\nBRz DONE\nLD R0, VALUE\nBRp DONE");
+        assert_eq!(warnings(&source, "`BR"), []);
+    }
+
+    #[test]
+    fn branches_after_calls() {
+        let source = program("JSR SUB\nBRz DONE\nGETC\nBRp DONE");
+        assert_eq!(
+            warnings(&source, "`BR"),
+            [
+                (
+                    3,
+                    "`BRz` tests condition codes left by the `JSR` on line 2; set them from the \
+                     result first, e.g. with `ADD R0, R0, #0`"
+                        .to_owned()
+                ),
+                (
+                    5,
+                    "`BRp` tests condition codes left by the `GETC` on line 4; set them from the \
+                     result first, e.g. with `ADD R0, R0, #0`"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn branches_after_stores_and_far_from_the_condition() {
+        let source = program(
+            "LD R0, VALUE\nADD R2, R0, #1\nST R2, SAVED\nBRz DONE\nST R0, SAVED\nBRz DONE\n\
+             ADD R0, R0, #0\nST R0, SAVED\nST R0, SAVED\nST R0, SAVED\nST R0, SAVED\n\
+             BRp DONE\nBR DONE",
+        );
+        assert_eq!(
+            warnings(&source, "`BR"),
+            [
+                (
+                    7,
+                    "`BRz` follows a store, which does not set condition codes, so it tests `R2` \
+                     from the `ADD` on line 3 rather than `R0`"
+                        .to_owned()
+                ),
+                (
+                    13,
+                    "`BRp` tests condition codes set from `R0` by the `ADD` on line 8, more than 3 \
+                     instructions earlier; set them again right before the branch"
+                        .to_owned()
+                ),
+                (
+                    14,
+                    "`BR` without `n`, `z` or `p` never branches; use `BRnzp` to always branch"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! Constant propagation that works out register values that are the same every time an
+//! instruction runs, e.g. the address `LEA R0, PROMPT` leaves in R0 for a following `PUTS`.
+//!
+//! Values come from `AND Rx, Rx, #0`, `ADD`, `AND` and `NOT` of known values, `LEA`, return
+//! addresses in R7, and loads from `.FILL` and `.STRINGZ` data that no store writes. A store whose
+//! address is not known could write any of it, so then no data counts as constant. Calls forget
+//! the registers the called subroutine may change. `build_cfg` feeds the targets of `JMP` and
+//! `JSRR` back into the control-flow graph, so the other checks follow them. There is no hover
+//! provider to show these yet, so `--print-cfg` lists the known values of the registers each
+//! instruction reads.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use super::cfg::{Cfg, EdgeKind, Instruction, Target};
+use super::headers::clobbered_by_subroutines;
+use crate::assembler::Program;
+use crate::ast::{DirectiveNodeValue, Node, NodeValue};
+use crate::simulator::sign_extend;
+
+/// Value of each register, if it is known.
+pub type Registers = [Option<u16>; 8];
+
+/// Builds the control-flow graph with the `JMP` and `JSRR` targets that constant propagation
+/// works out, along with the constants for it. Following a jump can make more code reachable and
+/// resolve more jumps, so this repeats until nothing changes. A jump whose target changes or
+/// stops being known along the way is left unresolved.
+pub fn build_cfg(program: &Program, nodes: &[Node]) -> (Cfg, Constants) {
+    let mut jumps = BTreeMap::<u16, u16>::new();
+    let mut unresolved = BTreeSet::<u16>::new();
+
+    loop {
+        let cfg = Cfg::build_with_jumps(program, nodes, &jumps);
+        let constants = Constants::analyze(&cfg, program, nodes);
+
+        let mut changed = false;
+        for instruction in cfg.instructions() {
+            let address = instruction.address;
+            if unresolved.contains(&address) || !constants.before.contains_key(&address) {
+                continue;
+            }
+            match (jumps.get(&address), constants.jump_target(instruction)) {
+                (None, None) => {}
+                (Some(&old), Some(new)) if old == new => {}
+                (None, Some(new)) => {
+                    jumps.insert(address, new);
+                    changed = true;
+                }
+                (Some(_), _) => {
+                    jumps.remove(&address);
+                    unresolved.insert(address);
+                    changed = true;
+                }
+            }
+        }
+
+        if !changed {
+            return (cfg, constants);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Constants {
+    /// Registers before the instruction at each address reachable from the entry.
+    before: BTreeMap<u16, Registers>,
+    /// Data words that loads can treat as constants.
+    constant_data: HashSet<u16>,
+    words: BTreeMap<u16, u16>,
+}
+
+impl Constants {
+    pub fn analyze(cfg: &Cfg, program: &Program, nodes: &[Node]) -> Self {
+        let mut constants = Constants {
+            before: BTreeMap::new(),
+            constant_data: (program.source_map.iter())
+                .filter(|(_, &node)| {
+                    matches!(
+                        nodes[node].value,
+                        NodeValue::Directive(
+                            DirectiveNodeValue::FILL(_) | DirectiveNodeValue::STRINGZ(_)
+                        )
+                    )
+                })
+                .map(|(&address, _)| address)
+                .collect(),
+            words: (program.source_map.keys())
+                .filter_map(|&address| Some((address, program.word_at(address)?)))
+                .collect(),
+        };
+
+        // Data that turns out to be written is no longer constant, which can make more stores'
+        // targets unknown, so this repeats until nothing else is written
+        loop {
+            constants.propagate(cfg);
+
+            let stores = (cfg.instructions())
+                .filter(|instruction| matches!(instruction.word >> 12, 0b0011 | 0b1011 | 0b0111))
+                .filter(|instruction| constants.before.contains_key(&instruction.address));
+            let mut written = Vec::new();
+            for store in stores {
+                match constants.store_target(store) {
+                    Some(address) if constants.constant_data.contains(&address) => {
+                        written.push(address)
+                    }
+                    Some(_) => {}
+                    None => written.extend(constants.constant_data.iter().copied()),
+                }
+            }
+
+            if written.is_empty() {
+                return constants;
+            }
+            for address in written {
+                constants.constant_data.remove(&address);
+            }
+        }
+    }
+
+    /// Registers just before the instruction at the address runs, or `None` if it is unreachable.
+    pub fn registers_before(&self, address: u16) -> Option<&Registers> {
+        self.before.get(&address)
+    }
+
+    /// Value of the register just before the instruction at the address runs, if it is the same
+    /// every time.
+    pub fn register_before(&self, address: u16, register: usize) -> Option<u16> {
+        self.registers_before(address)?[register]
+    }
+
+    /// Address a `JMP` or `JSRR` jumps to, if it is always the same. `RET` is left alone, since
+    /// where it goes depends on the caller.
+    pub fn jump_target(&self, instruction: &Instruction) -> Option<u16> {
+        let base_r = ((instruction.word >> 6) & 0x7) as usize;
+        match instruction.word >> 12 {
+            0b1100 if base_r == 7 => None,
+            0b1100 | 0b0100 if instruction.word & 0x800 == 0 => {
+                self.register_before(instruction.address, base_r)
+            }
+            _ => None,
+        }
+    }
+
+    /// Address an `ST`, `STI` or `STR` writes to, if it is always the same.
+    pub fn store_target(&self, instruction: &Instruction) -> Option<u16> {
+        match instruction.word >> 12 {
+            0b0011 => Some(instruction.pc_relative_target()),
+            0b1011 => self.load(instruction.pc_relative_target()),
+            0b0111 => {
+                let base_r = ((instruction.word >> 6) & 0x7) as usize;
+                let base = self.register_before(instruction.address, base_r)?;
+                Some(base.wrapping_add(sign_extend(instruction.word, 6)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Word at the address, if it is constant data.
+    fn load(&self, address: u16) -> Option<u16> {
+        self.constant_data
+            .contains(&address)
+            .then(|| self.words.get(&address).copied())
+            .flatten()
+    }
+
+    fn propagate(&mut self, cfg: &Cfg) {
+        self.before.clear();
+        let Some(entry) = cfg.entry else {
+            return;
+        };
+        let clobbered = clobbered_by_subroutines(cfg);
+
+        let mut states = vec![None::<Registers>; cfg.blocks.len()];
+        states[entry] = Some([None; 8]);
+        let mut pending = vec![entry];
+
+        while let Some(block) = pending.pop() {
+            let mut registers = states[block].unwrap();
+            for instruction in &cfg.blocks[block].instructions {
+                registers = self.registers_after(instruction, registers);
+            }
+
+            for edge in &cfg.blocks[block].successors {
+                let Target::Block(successor) = edge.target else {
+                    continue;
+                };
+
+                // Forget what the subroutine may change, or everything for `JSRR`
+                let mut registers = registers;
+                if edge.kind == EdgeKind::CallReturn {
+                    let changed = (cfg.callee(block)).map_or(0xFF, |callee| clobbered[&callee]);
+                    for (register, value) in registers.iter_mut().enumerate() {
+                        if changed & 1 << register != 0 {
+                            *value = None;
+                        }
+                    }
+                }
+
+                let merged = match states[successor] {
+                    Some(existing) => merge(existing, registers),
+                    None => registers,
+                };
+                if states[successor] != Some(merged) {
+                    states[successor] = Some(merged);
+                    pending.push(successor);
+                }
+            }
+        }
+
+        for (block, state) in cfg.blocks.iter().zip(states) {
+            let Some(mut registers) = state else {
+                continue;
+            };
+            for instruction in &block.instructions {
+                self.before.insert(instruction.address, registers);
+                registers = self.registers_after(instruction, registers);
+            }
+        }
+    }
+
+    fn registers_after(&self, instruction: &Instruction, mut registers: Registers) -> Registers {
+        let word = instruction.word;
+        let dr = ((word >> 9) & 0x7) as usize;
+        let sr1 = registers[((word >> 6) & 0x7) as usize];
+        let operand2 = match word & 0x20 {
+            0 => registers[(word & 0x7) as usize],
+            _ => Some(sign_extend(word, 5)),
+        };
+
+        match word >> 12 {
+            // ADD
+            0b0001 => registers[dr] = sr1.zip(operand2).map(|(a, b)| a.wrapping_add(b)),
+            // AND
+            0b0101 if operand2 == Some(0) => registers[dr] = Some(0),
+            0b0101 => registers[dr] = sr1.zip(operand2).map(|(a, b)| a & b),
+            // NOT
+            0b1001 => registers[dr] = sr1.map(|value| !value),
+            // LEA
+            0b1110 => registers[dr] = Some(instruction.pc_relative_target()),
+            // LD
+            0b0010 => registers[dr] = self.load(instruction.pc_relative_target()),
+            // LDI
+            0b1010 => {
+                let pointer = self.load(instruction.pc_relative_target());
+                registers[dr] = pointer.and_then(|pointer| self.load(pointer));
+            }
+            // LDR
+            0b0110 => {
+                let address = sr1.map(|base| base.wrapping_add(sign_extend(word, 6)));
+                registers[dr] = address.and_then(|address| self.load(address));
+            }
+            // JSR, JSRR, TRAP
+            0b0100 | 0b1111 => {
+                for register in instruction.writes() {
+                    registers[register] = None;
+                }
+                registers[7] = Some(instruction.address.wrapping_add(1));
+            }
+            _ => {}
+        }
+
+        registers
+    }
+}
+
+/// Keeps the values that agree.
+fn merge(a: Registers, b: Registers) -> Registers {
+    let mut merged = a;
+    for (value, other) in merged.iter_mut().zip(b) {
+        if *value != other {
+            *value = None;
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::cfg::Edge;
+    use crate::assembler;
+
+    fn build(source: &str) -> (Cfg, Constants) {
+        let (program, nodes) = assembler::assemble_source(source).unwrap();
+        build_cfg(&program, &nodes)
+    }
+
+    #[test]
+    fn loads_constant_data() {
+        let (_, constants) = build(
+            ".ORIG x3000\nLD R1, VALUE\nADD R2, R1, #1\nNOT R3, R2\nST R0, OTHER\nHALT\n\
+             VALUE .FILL #5\nOTHER .FILL #0\n.END",
+        );
+
+        assert_eq!(constants.register_before(0x3001, 1), Some(5));
+        assert_eq!(constants.register_before(0x3002, 2), Some(6));
+        assert_eq!(constants.register_before(0x3003, 3), Some(!6));
+        assert_eq!(constants.register_before(0x3003, 0), None);
+    }
+
+    #[test]
+    fn stores_to_unknown_addresses_make_data_non_constant() {
+        let (_, constants) = build(
+            ".ORIG x3000\nSTR R0, R2, #0\nLD R1, VALUE\nADD R2, R1, #1\nHALT\n\
+             VALUE .FILL #5\n.END",
+        );
+
+        assert_eq!(constants.register_before(0x3002, 1), None);
+    }
+
+    #[test]
+    fn stores_to_known_addresses_make_only_that_data_non_constant() {
+        let (_, constants) = build(
+            ".ORIG x3000\nLEA R2, VALUE\nSTR R0, R2, #0\nLD R1, VALUE\nLD R3, OTHER\nHALT\n\
+             VALUE .FILL #5\nOTHER .FILL #7\n.END",
+        );
+
+        assert_eq!(constants.register_before(0x3003, 1), None);
+        assert_eq!(constants.register_before(0x3004, 3), Some(7));
+    }
+
+    #[test]
+    fn resolves_jumps_into_the_cfg() {
+        let (cfg, constants) = build(
+            ".ORIG x3000\nLEA R3, SUB\nJSRR R3\nLEA R2, DONE\nJMP R2\nHALT\nSUB RET\n\
+             DONE HALT\n.END",
+        );
+
+        let jsrr = cfg.instruction_at(0x3001).unwrap();
+        assert_eq!(constants.jump_target(jsrr), Some(0x3005));
+        assert_eq!(
+            cfg.callee(cfg.block_of[&0x3001]),
+            Some(cfg.block_of[&0x3005])
+        );
+        assert!(cfg.subroutines.contains(&cfg.block_of[&0x3005]));
+
+        let jmp = cfg.instruction_at(0x3003).unwrap();
+        assert_eq!(constants.jump_target(jmp), Some(0x3006));
+        assert_eq!(
+            cfg.blocks[cfg.block_of[&0x3003]].successors,
+            [Edge {
+                kind: EdgeKind::Jump,
+                target: Target::Block(cfg.block_of[&0x3006]),
+            }]
+        );
+        // Only reachable by falling through the jump, which never happens
+        assert_eq!(constants.registers_before(0x3004), None);
+    }
+
+    #[test]
+    fn leaves_unknown_jumps_unresolved() {
+        let (cfg, constants) =
+            build(".ORIG x3000\nLD R3, TARGET\nJSRR R3\nHALT\nTARGET .BLKW #1\n.END");
+
+        let jsrr = cfg.instruction_at(0x3001).unwrap();
+        assert_eq!(constants.jump_target(jsrr), None);
+        assert_eq!(cfg.callee(cfg.block_of[&0x3001]), None);
+    }
+}
@@ -0,0 +1,355 @@
+//! Checks subroutines against the header comment above their label:
+//!
+//! ```text
+//! ; Push a value onto the stack.
+//! ;
+//! ; IN:  R0 (value to push)
+//! ; OUT: R5 (0-success, 1-fail/overflow)
+//! ;
+//! ; Register table:
+//! ; R3: stack end pointer
+//! ; R4: stack top pointer
+//! ;
+//! PUSH
+//! ```
+//!
+//! Declared inputs must be read, and declared outputs written on every path to `RET`, where
+//! loading a register back from where it was saved does not count as writing it. Any other
+//! register the subroutine changes, itself or through the subroutines it calls, must be saved and
+//! restored. The register table only documents how registers are used, so it does not exempt
+//! them. R6 and R7 are left to the stack and return address checks.
+
+use std::collections::BTreeMap;
+
+use super::cfg::Cfg;
+use super::uninitialized::{propagate, written_by_subroutines};
+use super::{is_restore, is_save};
+use crate::assembler::Program;
+use crate::ast::{Node, NodeError, NodeValue};
+
+/// Registers that the other checks cover.
+const IGNORED_REGISTERS: u8 = 1 << 6 | 1 << 7;
+
+#[derive(Debug, Default)]
+struct Header {
+    /// Registers declared on `IN:` lines and the comment nodes declaring them.
+    inputs: Vec<(usize, usize)>,
+    outputs: Vec<(usize, usize)>,
+    has_register_table: bool,
+}
+
+pub fn check(cfg: &Cfg, program: &Program, nodes: &mut [Node]) {
+    let written = written_by_subroutines(cfg, false);
+    let read = read_by_subroutines(cfg, &written);
+    // Outputs that are only saved and restored, here or in a callee, still hold the caller's value
+    let changed = written_by_subroutines(cfg, true);
+    let clobbered = clobbered_by_subroutines(cfg);
+
+    for &subroutine in &cfg.subroutines {
+        let start = cfg.blocks[subroutine].start();
+        let Some((label, header)) = find_header(program, nodes, start) else {
+            continue;
+        };
+
+        let reached = cfg.reachable(&[subroutine], false);
+        let local = || {
+            (cfg.blocks.iter().enumerate())
+                .filter(|&(block, _)| reached[block])
+                .flat_map(|(_, block)| &block.instructions)
+        };
+
+        for &(register, node) in &header.inputs {
+            if read[&subroutine] & 1 << register == 0 {
+                nodes[node].errors.push(NodeError::Warning(format!(
+                    "`R{}` is declared as an input of `{}`, but it is never read",
+                    register, label
+                )));
+            }
+        }
+
+        let always_written = changed.get(&subroutine).copied().unwrap_or(0);
+        for &(register, node) in &header.outputs {
+            if always_written & 1 << register == 0 {
+                nodes[node].errors.push(NodeError::Warning(format!(
+                    "`R{}` is declared as an output of `{}`, but it is not written on every \
+                     path to `RET`",
+                    register, label
+                )));
+            }
+        }
+
+        let outputs = (header.outputs.iter()).fold(0, |mask, &(register, _)| mask | 1 << register);
+        let unexpected = clobbered[&subroutine] & !outputs & !IGNORED_REGISTERS;
+        for register in (0..8).filter(|register| unexpected & 1 << register != 0) {
+            // Point at the first instruction that changes the register
+            let Some(instruction) = local().find(|instruction| {
+                let block = cfg.block_of[&instruction.address];
+                match cfg
+                    .callee(block)
+                    .filter(|_| instruction.word >> 12 == 0b0100)
+                {
+                    Some(callee) => clobbered[&callee] & 1 << register != 0,
+                    None => instruction.writes().contains(&register),
+                }
+            }) else {
+                continue;
+            };
+
+            nodes[instruction.node]
+                .errors
+                .push(NodeError::Warning(format!(
+                    "`R{}` is changed by `{}`, but it is not declared as an output or saved and \
+                     restored",
+                    register, label
+                )));
+        }
+    }
+}
+
+/// Registers each subroutine may read before writing them, itself or through the subroutines it
+/// calls, keyed by its entry block. Storing a register only to restore it later is not a read.
+fn read_by_subroutines(cfg: &Cfg, written: &BTreeMap<usize, u8>) -> BTreeMap<usize, u8> {
+    let mut summaries = (cfg.subroutines.iter())
+        .map(|&subroutine| (subroutine, 0))
+        .collect::<BTreeMap<usize, u8>>();
+
+    // Summaries start out empty and grow until they settle, which handles subroutines that call
+    // each other
+    loop {
+        let mut changed = false;
+        for &subroutine in &cfg.subroutines {
+            let mut read = 0;
+            let written_before = propagate(cfg, subroutine, 0, written, false, false);
+            for (block, state) in written_before.into_iter().enumerate() {
+                let Some(mut state) = state else {
+                    continue;
+                };
+
+                for instruction in &cfg.blocks[block].instructions {
+                    for (register, operand) in instruction.reads() {
+                        let saved = operand == Some(0) && is_save(cfg, instruction);
+                        if state & 1 << register == 0 && !saved {
+                            read |= 1 << register;
+                        }
+                    }
+                    for register in instruction.writes() {
+                        state |= 1 << register;
+                    }
+                }
+                if let Some(callee) = cfg.callee(block) {
+                    read |= summaries[&callee] & !state;
+                }
+            }
+
+            changed |= summaries.insert(subroutine, read) != Some(read);
+        }
+
+        if !changed {
+            return summaries;
+        }
+    }
+}
+
+/// Registers each subroutine may change by the time it returns, keyed by its entry block.
+/// Registers that are saved and restored within the subroutine are not counted.
+pub(super) fn clobbered_by_subroutines(cfg: &Cfg) -> BTreeMap<usize, u8> {
+    let mut summaries = (cfg.subroutines.iter())
+        .map(|&subroutine| (subroutine, 0))
+        .collect::<BTreeMap<usize, u8>>();
+
+    // Grows until it settles like `read_by_subroutines`
+    loop {
+        let mut changed = false;
+        for &subroutine in &cfg.subroutines {
+            let reached = cfg.reachable(&[subroutine], false);
+            let blocks = (cfg.blocks.iter().enumerate())
+                .filter(|&(block, _)| reached[block])
+                .map(|(_, block)| block);
+
+            let mut clobbered = 0;
+            let mut saved = 0;
+            let mut restored = 0;
+            for block in blocks {
+                for instruction in &block.instructions {
+                    for register in instruction.writes() {
+                        clobbered |= 1 << register;
+                    }
+                    if let Some((register, _)) = instruction.store_slot() {
+                        if is_save(cfg, instruction) {
+                            saved |= 1 << register;
+                        }
+                    }
+                    if let Some((register, _)) = instruction.load_slot() {
+                        if is_restore(cfg, instruction) {
+                            restored |= 1 << register;
+                        }
+                    }
+                }
+                if let Some(callee) = cfg.callee(cfg.block_of[&block.start()]) {
+                    clobbered |= summaries[&callee];
+                }
+            }
+
+            let summary = clobbered & !(saved & restored);
+            changed |= summaries.insert(subroutine, summary) != Some(summary);
+        }
+
+        if !changed {
+            return summaries;
+        }
+    }
+}
+
+/// Finds the header comment above a label at the address, returning the label and the header.
+/// The header is the run of comment lines directly above the label, and only counts if it has
+/// `IN:`, `OUT:` or register table lines.
+fn find_header(program: &Program, nodes: &[Node], address: u16) -> Option<(String, Header)> {
+    nodes.iter().enumerate().find_map(|(index, node)| {
+        let NodeValue::Label(label) = &node.value else {
+            return None;
+        };
+        if program.symbols.get(label) != Some(&address) {
+            return None;
+        }
+
+        let mut header = Header::default();
+        let mut previous_was_new_line = false;
+        for (comment_index, comment) in nodes[..index].iter().enumerate().rev() {
+            let text = match &comment.value {
+                NodeValue::NewLine if previous_was_new_line => break,
+                NodeValue::NewLine => {
+                    previous_was_new_line = true;
+                    continue;
+                }
+                NodeValue::Comment(text) => text.trim(),
+                _ => break,
+            };
+            previous_was_new_line = false;
+
+            let (key, rest) = text.split_once(':').unwrap_or((text, ""));
+            match key.trim().to_uppercase().as_str() {
+                "IN" | "INPUT" | "INPUTS" => header
+                    .inputs
+                    .extend(registers(rest).map(|register| (register, comment_index))),
+                "OUT" | "OUTPUT" | "OUTPUTS" => header
+                    .outputs
+                    .extend(registers(rest).map(|register| (register, comment_index))),
+                "REGISTER TABLE" => header.has_register_table = true,
+                _ => {}
+            }
+        }
+
+        let found =
+            !header.inputs.is_empty() || !header.outputs.is_empty() || header.has_register_table;
+        found.then(|| (label.clone(), header))
+    })
+}
+
+/// Registers listed at the start of the text, e.g. R0 and R1 for `R0, R1 (operands)`.
+fn registers(text: &str) -> impl Iterator<Item = usize> + '_ {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .map_while(|word| match word.to_uppercase().as_bytes() {
+            [b'R', digit @ b'0'..=b'7'] => Some((digit - b'0') as usize),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::warnings;
+
+    fn program(subroutines: &str) -> String {
+        format!(
+            ".ORIG x3000\nAND R0, R0, #0\nJSR GETV\nADD R3, R3, #0\nHALT\n{}\n.END",
+            subroutines
+        )
+    }
+
+    #[test]
+    fn outputs_written_on_every_path() {
+        let source = program(
+            "; OUT: R3 (value)\nGETV ADD R0, R0, #0\nBRz ZERO\nAND R3, R3, #0\nRET\n\
+             ZERO ADD R3, R0, #1\nRET",
+        );
+        assert_eq!(warnings(&source, "is declared"), []);
+    }
+
+    #[test]
+    fn outputs_missing_on_a_path() {
+        let source = program(
+            "; IN:  R0 (flag)\n; IN:  R1 (unused)\n; OUT: R3 (value)\n\
+             GETV ADD R0, R0, #0\nBRz ZERO\nAND R3, R3, #0\nZERO RET",
+        );
+        assert_eq!(
+            warnings(&source, "is declared"),
+            [
+                (
+                    7,
+                    "`R1` is declared as an input of `GETV`, but it is never read".to_owned()
+                ),
+                (
+                    8,
+                    "`R3` is declared as an output of `GETV`, but it is not written on every \
+                     path to `RET`"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn outputs_only_restored_by_a_callee() {
+        let source = program(
+            "; OUT: R3 (value)\nGETV ST R7, GETV_R7\nJSR HELPER\nLD R7, GETV_R7\nRET\n\
+             GETV_R7 .BLKW #1\n\
+             HELPER ST R3, HELPER_R3\nAND R3, R3, #0\nLD R3, HELPER_R3\nRET\n\
+             HELPER_R3 .BLKW #1",
+        );
+        assert_eq!(
+            warnings(&source, "is declared"),
+            [(
+                6,
+                "`R3` is declared as an output of `GETV`, but it is not written on every path \
+                 to `RET`"
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn outputs_only_restored_on_an_early_return() {
+        let source = program(
+            "; OUT: R3 (value)\nGETV ST R7, GETV_R7\nJSR HELPER\nADD R0, R0, #0\n\
+             BRz DONE\nADD R3, R0, #0\nDONE LD R7, GETV_R7\nRET\nGETV_R7 .BLKW #1\n\
+             HELPER ST R3, HELPER_R3\nAND R3, R3, #0\nLD R3, HELPER_R3\nRET\n\
+             HELPER_R3 .BLKW #1",
+        );
+        assert_eq!(
+            warnings(&source, "is declared as an output"),
+            [(
+                6,
+                "`R3` is declared as an output of `GETV`, but it is not written on every path \
+                 to `RET`"
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn registers_changed_without_being_saved() {
+        let source = program(
+            "; OUT: R3 (value)\nGETV ST R1, GETV_R1\nAND R1, R1, #0\nADD R2, R1, #1\n\
+             ADD R3, R2, #0\nLD R1, GETV_R1\nRET\nGETV_R1 .BLKW #1",
+        );
+        assert_eq!(
+            warnings(&source, "changed by"),
+            [(
+                9,
+                "`R2` is changed by `GETV`, but it is not declared as an output or saved and \
+                 restored"
+                    .to_owned()
+            )]
+        );
+    }
+}
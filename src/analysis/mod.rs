@@ -0,0 +1,146 @@
+//! Checks that follow the control flow of an assembled program, rather than looking at each
+//! node on its own like `passes`.
+
+pub mod cfg;
+mod condition_codes;
+pub mod constants;
+mod headers;
+mod reachability;
+mod return_address;
+mod self_modifying;
+mod stack;
+mod termination;
+mod uninitialized;
+
+use crate::assembler::Program;
+use crate::ast::{Node, NodeError};
+use crate::disassembler::disassemble;
+use cfg::{Cfg, Instruction, Slot};
+
+/// Runs the control-flow checks, adding warnings to the nodes. The program should have assembled
+/// without errors.
+pub fn analyze(program: &Program, nodes: &mut [Node]) {
+    let (cfg, constants) = constants::build_cfg(program, nodes);
+    reachability::check(&cfg, program, nodes);
+    uninitialized::check(&cfg, nodes);
+    return_address::check(&cfg, nodes);
+    headers::check(&cfg, program, nodes);
+    condition_codes::check(&cfg, nodes);
+    stack::check(&cfg, nodes);
+    termination::check(&cfg, program, nodes);
+    self_modifying::check(&cfg, program, &constants, nodes);
+}
+
+/// Assembles the source and runs the checks, returning the line and message of every warning
+/// whose message contains the text.
+#[cfg(test)]
+fn warnings(source: &str, text: &str) -> Vec<(usize, String)> {
+    let (program, mut nodes) = crate::assembler::assemble_source(source).unwrap();
+    analyze(&program, &mut nodes);
+
+    (nodes.iter())
+        .flat_map(|node| node.errors.iter().map(|error| (node.start_loc.line, error)))
+        .filter_map(|(line, error)| match error {
+            NodeError::Warning(message) | NodeError::WarningAt(_, _, message) => {
+                message.contains(text).then(|| (line, message.clone()))
+            }
+            NodeError::Error(message) => panic!("error on line {}: {}", line, message),
+        })
+        .collect()
+}
+
+/// Names the address by its label if it has one, e.g. `` `PROMPT` `` or `x3010`.
+fn describe_address(program: &Program, address: u16) -> String {
+    program
+        .symbols
+        .iter()
+        .filter(|(_, &label_address)| label_address == address)
+        .map(|(label, _)| format!("`{}`", label))
+        .min()
+        .unwrap_or(format!("x{:04X}", address))
+}
+
+/// Whether the instruction is an `ST` or `STR` that saves its register, i.e. the program loads
+/// the register back from the same slot. Only labeled slots and the R6 stack are used for saving,
+/// since other base registers point at data structures.
+fn is_save(cfg: &Cfg, store: &Instruction) -> bool {
+    store.store_slot().is_some_and(|slot| {
+        is_save_slot(slot)
+            && cfg
+                .instructions()
+                .any(|instruction| instruction.load_slot() == Some(slot))
+    })
+}
+
+/// Whether the instruction is an `LD` or `LDR` that restores its register, i.e. the program
+/// stores the register to the same slot.
+fn is_restore(cfg: &Cfg, load: &Instruction) -> bool {
+    load.load_slot().is_some_and(|slot| {
+        is_save_slot(slot)
+            && cfg
+                .instructions()
+                .any(|instruction| instruction.store_slot() == Some(slot))
+    })
+}
+
+fn is_save_slot((_, slot): (usize, Slot)) -> bool {
+    matches!(slot, Slot::Address(_) | Slot::Offset(6, _))
+}
+
+/// Name of the instruction, e.g. `BRnzp` or `PUTS`.
+fn mnemonic(instruction: &Instruction) -> String {
+    let text = disassemble(instruction.word, instruction.address);
+    text.split(' ').next().unwrap_or_default().to_owned()
+}
+
+/// Adds the warning to the node unless it already has it, e.g. for code shared by subroutines
+/// that are checked separately.
+fn warn_once(node: &mut Node, message: String) {
+    let reported = (node.errors.iter())
+        .any(|error| matches!(error, NodeError::Warning(warning) if *warning == message));
+    if !reported {
+        node.errors.push(NodeError::Warning(message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_program_has_no_warnings() {
+        let source = "
+            .ORIG x3000
+                    LEA R0, PROMPT
+                    PUTS
+                    AND R1, R1, #0
+                    ADD R1, R1, #2
+                    JSR DOUBLE
+                    ADD R1, R1, #0
+                    BRz DONE
+                    ST R1, RESULT
+            DONE    HALT
+
+            ; Doubles a number.
+            ;
+            ; IN:  R1 (number)
+            ; OUT: R1 (number times two)
+            ;
+            DOUBLE  ADD R1, R1, R1
+                    RET
+
+            PROMPT  .STRINGZ \"Doubling\"
+            RESULT  .BLKW #1
+            .END
+        ";
+        assert_eq!(warnings(source, ""), []);
+    }
+
+    #[test]
+    fn describes_addresses_by_label() {
+        let (program, _) =
+            crate::assembler::assemble_source(".ORIG x3000\nHALT\nDATA .FILL #0\n.END").unwrap();
+        assert_eq!(describe_address(&program, 0x3001), "`DATA`");
+        assert_eq!(describe_address(&program, 0x3002), "x3002");
+    }
+}
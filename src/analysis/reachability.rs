@@ -0,0 +1,138 @@
+//! Warnings for code that can never execute and for execution that runs into data.
+
+use std::collections::HashSet;
+
+use super::cfg::{Cfg, EdgeKind, Target};
+use super::{describe_address, mnemonic};
+use crate::assembler::Program;
+use crate::ast::{Node, NodeError};
+
+pub fn check(cfg: &Cfg, program: &Program, nodes: &mut [Node]) {
+    // Labeled code may be reached through `JSRR`, `JMP` or a vector table, so it counts as
+    // reachable along with the entry.
+    let labeled = program.symbols.values().collect::<HashSet<_>>();
+    let roots = (0..cfg.blocks.len())
+        .filter(|&i| cfg.entry == Some(i) || labeled.contains(&cfg.blocks[i].start()))
+        .collect::<Vec<usize>>();
+    let reached = cfg.reachable(&roots, true);
+
+    for (i, block) in cfg.blocks.iter().enumerate() {
+        if reached[i] {
+            continue;
+        }
+
+        // Only the first block of a run of unreachable code is reported
+        let previous = cfg.block_of.get(&block.start().wrapping_sub(1));
+        if previous.is_some_and(|&previous| !reached[previous] && previous != i) {
+            continue;
+        }
+
+        let message = match previous {
+            Some(&previous) => format!(
+                "Unreachable code after `{}`",
+                mnemonic(cfg.blocks[previous].last())
+            ),
+            None => "Unreachable code".to_owned(),
+        };
+        nodes[block.instructions[0].node]
+            .errors
+            .push(NodeError::Warning(message));
+    }
+
+    for block in &cfg.blocks {
+        for edge in &block.successors {
+            let message = match (edge.kind, edge.target) {
+                (_, Target::Block(_)) => continue,
+                (EdgeKind::FallThrough | EdgeKind::CallReturn, Target::Data(address)) => format!(
+                    "Execution continues into data at {}; is a `HALT` or `RET` missing?",
+                    describe_address(program, address)
+                ),
+                (EdgeKind::FallThrough | EdgeKind::CallReturn, Target::Outside(_)) => {
+                    "Execution continues past the end of the section; is a `HALT` or `RET` \
+                     missing?"
+                        .to_owned()
+                }
+                (_, Target::Data(address)) => {
+                    format!("Jumps into data at {}", describe_address(program, address))
+                }
+                (_, Target::Outside(address)) => {
+                    format!("Jumps to x{:04X}, which is outside the program", address)
+                }
+            };
+            nodes[block.last().node]
+                .errors
+                .push(NodeError::Warning(message));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::warnings;
+
+    #[test]
+    fn reports_unreachable_code_and_data_reached_by_execution() {
+        let source = "
+            .ORIG x3000
+                    BRnzp SKIP
+                    ADD R0, R0, #1
+                    ADD R0, R0, #2
+            SKIP    AND R1, R1, #0
+                    BRz DATA
+                    ADD R1, R1, #1
+            DATA    .FILL #0
+            .END
+        ";
+
+        assert_eq!(
+            warnings(source, "Unreachable"),
+            [(4, "Unreachable code after `BRnzp`".to_owned())]
+        );
+        assert_eq!(
+            warnings(source, "data"),
+            [
+                (7, "Jumps into data at `DATA`".to_owned()),
+                (
+                    8,
+                    "Execution continues into data at `DATA`; is a `HALT` or `RET` missing?"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn labeled_code_counts_as_reachable() {
+        let source = "
+            .ORIG x3000
+                    HALT
+            HANDLER AND R0, R0, #0
+                    RTI
+            .END
+        ";
+
+        assert_eq!(warnings(source, "Unreachable"), []);
+        assert_eq!(warnings(source, "continues"), []);
+    }
+
+    #[test]
+    fn reports_running_off_the_end_of_a_section() {
+        assert_eq!(
+            warnings(".ORIG x3000\nAND R0, R0, #0\n.END", "past the end"),
+            [(
+                2,
+                "Execution continues past the end of the section; is a `HALT` or `RET` missing?"
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn follows_resolved_jumps() {
+        let source = ".ORIG x3000\nLEA R2, DONE\nJMP R2\nADD R0, R0, #1\nDONE HALT\n.END";
+        assert_eq!(
+            warnings(source, "Unreachable"),
+            [(4, "Unreachable code after `JMP`".to_owned())]
+        );
+    }
+}
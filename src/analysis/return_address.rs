@@ -0,0 +1,143 @@
+//! Warnings for subroutines that overwrite their return address in R7 before returning.
+//!
+//! `JSR`, `JSRR` and `TRAP` all write their own return address to R7, so a subroutine that calls
+//! anything has to save R7 first and restore it before `RET`, or `RET` jumps back to just after
+//! its own last call.
+
+use super::cfg::{Cfg, Exit, Slot};
+use super::{is_save, mnemonic, warn_once};
+use crate::ast::Node;
+
+/// What is known about R7 at a point in a subroutine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct State {
+    /// Node of the instruction that first overwrote the return address, on some path.
+    clobbered_by: Option<usize>,
+    /// Where the return address has been saved, if it was saved to the same place on every path.
+    saved_to: Option<Slot>,
+}
+
+impl State {
+    fn merge(self, other: State) -> State {
+        State {
+            clobbered_by: self.clobbered_by.or(other.clobbered_by),
+            saved_to: self.saved_to.filter(|&slot| other.saved_to == Some(slot)),
+        }
+    }
+}
+
+pub fn check(cfg: &Cfg, nodes: &mut [Node]) {
+    for &subroutine in &cfg.subroutines {
+        let mut states = vec![None; cfg.blocks.len()];
+        states[subroutine] = Some(State {
+            clobbered_by: None,
+            saved_to: None,
+        });
+        let mut pending = vec![subroutine];
+
+        while let Some(block) = pending.pop() {
+            let state = state_after(cfg, block, states[block].unwrap());
+            for successor in cfg.local_successors(block) {
+                let merged =
+                    states[successor].map_or(state, |existing: State| existing.merge(state));
+                if states[successor] != Some(merged) {
+                    states[successor] = Some(merged);
+                    pending.push(successor);
+                }
+            }
+        }
+
+        for (block, state) in states.into_iter().enumerate() {
+            let Some(state) = state else {
+                continue;
+            };
+            if cfg.blocks[block].exit != Exit::Return {
+                continue;
+            }
+            let Some(clobbered_by) = state_after(cfg, block, state).clobbered_by else {
+                continue;
+            };
+
+            let clobber = (cfg.instructions())
+                .find(|instruction| instruction.node == clobbered_by)
+                .unwrap();
+            let message = format!(
+                "`RET` may not return to the caller, since `R7` is overwritten by the `{}` on \
+                 line {} and not restored",
+                mnemonic(clobber),
+                nodes[clobbered_by].start_loc.line
+            );
+
+            warn_once(&mut nodes[cfg.blocks[block].last().node], message);
+        }
+    }
+}
+
+/// State of R7 at the end of the block, given the state at its start.
+fn state_after(cfg: &Cfg, block: usize, mut state: State) -> State {
+    for instruction in &cfg.blocks[block].instructions {
+        if let Some((7, slot)) = instruction.store_slot() {
+            if state.clobbered_by.is_none() && is_save(cfg, instruction) {
+                state.saved_to = Some(slot);
+            }
+        }
+
+        if instruction.writes().contains(&7) {
+            // Only loading back from where it was saved restores it
+            let restored =
+                (state.saved_to).is_some_and(|slot| instruction.load_slot() == Some((7, slot)));
+            state.clobbered_by = match restored {
+                true => None,
+                false => state.clobbered_by.or(Some(instruction.node)),
+            };
+        }
+    }
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::warnings;
+
+    fn program(outer: &str) -> String {
+        format!(
+            ".ORIG x3000\nJSR OUTER\nHALT\nOUTER {}\nINNER RET\nSAVE_R7 .BLKW #1\nOTHER .BLKW #1\n.END",
+            outer
+        )
+    }
+
+    #[test]
+    fn restoring_from_the_same_slot() {
+        let source = program("ST R7, SAVE_R7\nJSR INNER\nLD R7, SAVE_R7\nRET");
+        assert_eq!(warnings(&source, "`RET` may not return"), []);
+    }
+
+    #[test]
+    fn restoring_from_a_different_slot() {
+        let source = program("ST R7, SAVE_R7\nJSR INNER\nLD R7, OTHER\nRET");
+        assert_eq!(
+            warnings(&source, "`RET` may not return"),
+            [(
+                7,
+                "`RET` may not return to the caller, since `R7` is overwritten by the `JSR` on \
+                 line 5 and not restored"
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn calling_without_saving() {
+        let source = program("JSR INNER\nTRAP x21\nRET");
+        assert_eq!(
+            warnings(&source, "`RET` may not return"),
+            [(
+                6,
+                "`RET` may not return to the caller, since `R7` is overwritten by the `JSR` on \
+                 line 4 and not restored"
+                    .to_owned()
+            )]
+        );
+    }
+}
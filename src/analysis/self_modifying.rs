@@ -0,0 +1,228 @@
+//! Warnings for stores that overwrite instructions, and for `.BLKW` buffers printed with `PUTS`
+//! that can run into code or that nothing ever writes.
+//!
+//! Store targets come from constant propagation, so a store through a pointer that changes, like
+//! one walking along a buffer, is only checked when its value is known. An `STR` in a loop, e.g.
+//! one filling a buffer from `GETC`, is assumed to be able to fill every buffer whose start its
+//! base register holds somewhere, leaving no terminating x0000 for `PUTS` inside it. A buffer
+//! counts as never written only when every reachable store has a known target outside it.
+
+use std::collections::BTreeSet;
+
+use super::cfg::Cfg;
+use super::constants::Constants;
+use super::{describe_address, mnemonic, warn_once};
+use crate::assembler::Program;
+use crate::ast::{DirectiveNodeValue, Node, NodeValue};
+
+/// Trap vector of `PUTS`.
+const PUTS: u16 = 0x22;
+
+pub fn check(cfg: &Cfg, program: &Program, constants: &Constants, nodes: &mut [Node]) {
+    for instruction in cfg.instructions() {
+        let Some(target) = constants.store_target(instruction) else {
+            continue;
+        };
+        let Some(overwritten) = cfg.instruction_at(target) else {
+            continue;
+        };
+
+        let message = format!(
+            "`{}` overwrites the `{}` at {} on line {}, changing the program while it runs",
+            mnemonic(instruction),
+            mnemonic(overwritten),
+            describe_address(program, target),
+            nodes[overwritten.node].start_loc.line
+        );
+        warn_once(&mut nodes[instruction.node], message);
+    }
+
+    let pointers = looping_store_bases(cfg, constants);
+    for instruction in cfg.instructions() {
+        if instruction.word != 0xF000 | PUTS {
+            continue;
+        }
+        let Some(string) = constants.register_before(instruction.address, 0) else {
+            continue;
+        };
+        let Some(&buffer) = program.source_map.get(&string) else {
+            continue;
+        };
+        if !matches!(
+            nodes[buffer].value,
+            NodeValue::Directive(DirectiveNodeValue::BLKW(_))
+        ) {
+            continue;
+        }
+
+        // Every word of the `.BLKW` maps to its node, so the buffer ends where they stop
+        let last = (program.source_map.range(string..))
+            .take_while(|&(_, &node)| node == buffer)
+            .map(|(&address, _)| address)
+            .last()
+            .unwrap_or(string);
+
+        let stores = (cfg.instructions())
+            .filter(|store| matches!(store.word >> 12, 0b0011 | 0b1011 | 0b0111))
+            .filter(|store| constants.registers_before(store.address).is_some());
+        let may_write = stores
+            .map(|store| constants.store_target(store))
+            .any(|target| target.is_none_or(|target| (string..=last).contains(&target)));
+        if !may_write {
+            let message = format!(
+                "`PUTS` prints the `.BLKW` buffer at {}, which the program never writes, so it \
+                 always prints an empty string",
+                describe_address(program, string)
+            );
+            warn_once(&mut nodes[instruction.node], message);
+            continue;
+        }
+
+        let filled_by_store =
+            (cfg.instructions()).any(|store| constants.store_target(store) == Some(last));
+        let filled_by_loop = (cfg.instructions()).any(|other| {
+            let Some(registers) = constants.registers_before(other.address) else {
+                return false;
+            };
+            (pointers.iter()).any(|&base| registers[base] == Some(string))
+        });
+        if !filled_by_store && !filled_by_loop {
+            continue;
+        }
+
+        // `PUTS` keeps going until it finds a x0000 word after the buffer
+        let Some(code) = first_code_before_terminator(cfg, program, last.wrapping_add(1)) else {
+            continue;
+        };
+        let message = format!(
+            "`PUTS` prints the `.BLKW` buffer at {}, which the program can fill completely; \
+             without a terminating x0000 inside it, `PUTS` runs into the code at {}",
+            describe_address(program, string),
+            describe_address(program, code)
+        );
+        warn_once(&mut nodes[instruction.node], message);
+    }
+}
+
+/// Base registers of the reachable `STR`s in loops whose target is not known.
+fn looping_store_bases(cfg: &Cfg, constants: &Constants) -> BTreeSet<usize> {
+    let mut bases = BTreeSet::new();
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        let in_loop = (cfg.local_successors(index))
+            .any(|successor| cfg.reachable(&[successor], false)[index]);
+        if !in_loop {
+            continue;
+        }
+
+        for store in &block.instructions {
+            let is_str = store.word >> 12 == 0b0111;
+            let reachable = constants.registers_before(store.address).is_some();
+            if is_str && reachable && constants.store_target(store).is_none() {
+                bases.insert(usize::from(store.word >> 6 & 0x7));
+            }
+        }
+    }
+    bases
+}
+
+/// First instruction from the address onward that comes before any x0000 word, if the program
+/// has no gaps up to it.
+fn first_code_before_terminator(cfg: &Cfg, program: &Program, start: u16) -> Option<u16> {
+    let mut address = start;
+    loop {
+        if cfg.instruction_at(address).is_some() {
+            return Some(address);
+        }
+        match program.word_at(address)? {
+            0 => return None,
+            _ => address = address.wrapping_add(1),
+        }
+        if address == start {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::warnings;
+
+    #[test]
+    fn stores_into_code() {
+        let source =
+            ".ORIG x3000\nLEA R1, NEXT\nSTR R0, R1, #0\nNEXT ADD R0, R0, #1\nST R0, DATA\n\
+             HALT\nDATA .FILL #0\n.END";
+        assert_eq!(
+            warnings(source, "overwrites"),
+            [(
+                3,
+                "`STR` overwrites the `ADD` at `NEXT` on line 4, changing the program while it \
+                 runs"
+                    .to_owned()
+            )]
+        );
+    }
+
+    /// Reads a line into the buffer, which is followed by the given lines, and prints it.
+    fn read_and_print(buffer: &str, after: &str) -> String {
+        format!(
+            ".ORIG x3000\nLEA R1, BUFFER\nAND R2, R2, #0\nLOOP GETC\nSTR R0, R1, #0\n\
+             ADD R1, R1, #1\nADD R0, R0, #-10\nBRnp LOOP\nLEA R0, {}\nPUTS\nHALT\n\
+             BUFFER .BLKW #4\nMESSAGE .STRINGZ \"hi\"\nEMPTY .BLKW #2\n{}\n.END",
+            buffer, after
+        )
+    }
+
+    #[test]
+    fn prints_strings_and_terminated_buffers() {
+        assert_eq!(warnings(&read_and_print("MESSAGE", ""), "PUTS"), []);
+        // The x0000 ending `MESSAGE` also ends the buffer's string when the buffer is full
+        let source = read_and_print("BUFFER", "NEXT HALT");
+        assert_eq!(warnings(&source, "PUTS"), []);
+    }
+
+    #[test]
+    fn prints_buffers_never_written() {
+        let source = ".ORIG x3000\nST R0, SAVED\nLEA R0, EMPTY\nPUTS\nHALT\nSAVED .BLKW #1\n\
+                      EMPTY .BLKW #2\n.END";
+        assert_eq!(
+            warnings(source, "PUTS"),
+            [(
+                4,
+                "`PUTS` prints the `.BLKW` buffer at `EMPTY`, which the program never writes, so \
+                 it always prints an empty string"
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn prints_buffers_that_run_into_code() {
+        let source = read_and_print("EMPTY", "LD R0, BUFFER\nRET")
+            .replace("LEA R1, BUFFER", "LEA R1, EMPTY");
+        assert_eq!(
+            warnings(&source, "PUTS"),
+            [(
+                10,
+                "`PUTS` prints the `.BLKW` buffer at `EMPTY`, which the program can fill \
+                 completely; without a terminating x0000 inside it, `PUTS` runs into the code at \
+                 x3013"
+                    .to_owned()
+            )]
+        );
+
+        // Non-zero data between the buffer and the code does not end the string
+        let source = read_and_print("EMPTY", ".FILL #1\n.FILL #2\nNEXT HALT")
+            .replace("LEA R1, BUFFER", "LEA R1, EMPTY");
+        assert_eq!(
+            warnings(&source, "PUTS"),
+            [(
+                10,
+                "`PUTS` prints the `.BLKW` buffer at `EMPTY`, which the program can fill \
+                 completely; without a terminating x0000 inside it, `PUTS` runs into the code at \
+                 `NEXT`"
+                    .to_owned()
+            )]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Warnings for subroutines that use R6 as a stack pointer without keeping the stack balanced.
+//!
+//! The stack grows down, so pushing is `ADD R6, R6, #-1` followed by `STR Rx, R6, #0`. Within
+//! each subroutine, R6 is tracked as an offset from its value on entry. Every `RET` should see an
+//! offset of zero, `LDR` and `STR` must not reach below the top of the stack, and `STR` must not
+//! write into the caller's part of the stack above the entry value. `LDR` may read there, which is
+//! how arguments passed on the stack are accessed.
+
+use super::cfg::{Cfg, Exit, Instruction, Slot};
+use super::warn_once;
+use crate::ast::Node;
+use crate::simulator::sign_extend;
+
+const STACK_POINTER: usize = 6;
+
+/// What is known about R6 at a point in a subroutine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Offset {
+    /// R6 is its value on entry plus the offset.
+    Known(i32),
+    /// Paths with different offsets meet here.
+    Mismatch,
+    /// R6 was set in a way that is not tracked, e.g. loaded from memory.
+    Unknown,
+}
+
+impl Offset {
+    fn merge(self, other: Offset) -> Offset {
+        match (self, other) {
+            (Offset::Known(a), Offset::Known(b)) if a == b => self,
+            (Offset::Unknown, _) | (_, Offset::Unknown) => Offset::Unknown,
+            _ => Offset::Mismatch,
+        }
+    }
+}
+
+pub fn check(cfg: &Cfg, nodes: &mut [Node]) {
+    for &subroutine in &cfg.subroutines {
+        let reached = cfg.reachable(&[subroutine], false);
+        let uses_stack = (cfg.blocks.iter().enumerate())
+            .filter(|&(block, _)| reached[block])
+            .flat_map(|(_, block)| &block.instructions)
+            .any(|instruction| {
+                instruction.writes().contains(&STACK_POINTER) || stack_offset(instruction).is_some()
+            });
+        if !uses_stack {
+            continue;
+        }
+
+        let mut offsets = vec![None; cfg.blocks.len()];
+        offsets[subroutine] = Some(Offset::Known(0));
+        let mut pending = vec![subroutine];
+
+        while let Some(block) = pending.pop() {
+            let offset =
+                (cfg.blocks[block].instructions.iter()).fold(offsets[block].unwrap(), offset_after);
+
+            for successor in cfg.local_successors(block) {
+                let merged =
+                    offsets[successor].map_or(offset, |existing: Offset| existing.merge(offset));
+                if offsets[successor] != Some(merged) {
+                    offsets[successor] = Some(merged);
+                    pending.push(successor);
+                }
+            }
+        }
+
+        for (block, offset) in cfg.blocks.iter().zip(offsets) {
+            let Some(mut offset) = offset else {
+                continue;
+            };
+
+            for instruction in &block.instructions {
+                if let (Offset::Known(top), Some(access)) = (offset, stack_offset(instruction)) {
+                    let message = if access < 0 {
+                        Some(format!(
+                            "Accesses {} below the top of the stack, which has not been pushed",
+                            words(-access)
+                        ))
+                    } else if top + access >= 0 && instruction.store_slot().is_some() {
+                        Some(
+                            "Writes into the caller's part of the stack, above what this \
+                             subroutine has pushed"
+                                .to_owned(),
+                        )
+                    } else {
+                        None
+                    };
+                    if let Some(message) = message {
+                        warn_once(&mut nodes[instruction.node], message);
+                    }
+                }
+
+                offset = offset_after(offset, instruction);
+            }
+
+            if block.exit != Exit::Return {
+                continue;
+            }
+            let message = match offset {
+                Offset::Known(0) | Offset::Unknown => continue,
+                Offset::Known(offset) if offset < 0 => format!(
+                    "`RET` leaves {} pushed onto the stack that should be popped first",
+                    words(-offset)
+                ),
+                Offset::Known(offset) => format!(
+                    "`RET` leaves {} more popped off the stack than were pushed",
+                    words(offset)
+                ),
+                Offset::Mismatch => {
+                    "`RET` is reached with different amounts pushed onto the stack on different \
+                     paths"
+                        .to_owned()
+                }
+            };
+            warn_once(&mut nodes[block.last().node], message);
+        }
+    }
+}
+
+/// Offset of R6 after the instruction, given the offset before it.
+fn offset_after(offset: Offset, instruction: &Instruction) -> Offset {
+    let word = instruction.word;
+    let dr = ((word >> 9) & 0x7) as usize;
+    let sr1 = ((word >> 6) & 0x7) as usize;
+
+    if !instruction.writes().contains(&STACK_POINTER) {
+        return offset;
+    }
+
+    // ADD R6, R6, #imm5
+    let is_adjustment = word >> 12 == 0b0001 && dr == STACK_POINTER && sr1 == STACK_POINTER;
+    match offset {
+        Offset::Known(offset) if is_adjustment && word & 0x20 != 0 => {
+            Offset::Known(offset + sign_extend(word, 5) as i16 as i32)
+        }
+        Offset::Mismatch if is_adjustment && word & 0x20 != 0 => Offset::Mismatch,
+        _ => Offset::Unknown,
+    }
+}
+
+/// Offset from R6 that an `LDR` or `STR` with R6 as its base accesses.
+fn stack_offset(instruction: &Instruction) -> Option<i32> {
+    let slot = instruction.load_slot().or(instruction.store_slot());
+    match slot {
+        Some((_, Slot::Offset(STACK_POINTER, offset))) => Some(offset as i16 as i32),
+        _ => None,
+    }
+}
+
+fn words(count: i32) -> String {
+    match count {
+        1 => "1 word".to_owned(),
+        count => format!("{} words", count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::warnings;
+
+    fn program(subroutine: &str) -> String {
+        format!(
+            ".ORIG x3000\nLD R6, STACK\nJSR SUB\nHALT\nSTACK .FILL xFE00\nSUB {}\n.END",
+            subroutine
+        )
+    }
+
+    #[test]
+    fn balanced_pushes_and_pops() {
+        let source = program(
+            "ADD R6, R6, #-1\nSTR R7, R6, #0\nLDR R0, R6, #1\nLDR R7, R6, #0\nADD R6, R6, #1\nRET",
+        );
+        assert_eq!(warnings(&source, "stack"), []);
+    }
+
+    #[test]
+    fn reports_unbalanced_returns() {
+        let source = program("ADD R6, R6, #-2\nSTR R7, R6, #0\nADD R6, R6, #1\nRET");
+        assert_eq!(
+            warnings(&source, "stack"),
+            [(
+                9,
+                "`RET` leaves 1 word pushed onto the stack that should be popped first".to_owned()
+            )]
+        );
+
+        let source = program("ADD R0, R0, #0\nBRz SKIP\nADD R6, R6, #-1\nSKIP RET");
+        assert_eq!(
+            warnings(&source, "stack"),
+            [(
+                9,
+                "`RET` is reached with different amounts pushed onto the stack on different paths"
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_accesses_outside_the_frame() {
+        let source = program("LDR R0, R6, #-1\nSTR R0, R6, #0\nRET");
+        assert_eq!(
+            warnings(&source, "stack"),
+            [
+                (
+                    6,
+                    "Accesses 1 word below the top of the stack, which has not been pushed"
+                        .to_owned()
+                ),
+                (
+                    7,
+                    "Writes into the caller's part of the stack, above what this subroutine has \
+                     pushed"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Warnings for loops that can never end and for programs that can never reach `HALT`.
+//!
+//! A loop is reported when nothing in it branches out, returns, halts or jumps elsewhere, and it
+//! does no console I/O or calls, which could be waiting on the user or halting in a subroutine.
+//! Loops that only end once a register changes are not checked. `JMP` and `JSRR` are followed when
+//! constant propagation resolves them, and the program may halt wherever one that is not resolved
+//! goes.
+
+use super::cfg::{Cfg, Exit, Target};
+use super::{describe_address, warn_once};
+use crate::assembler::Program;
+use crate::ast::{DirectiveNodeValue, Node, NodeValue};
+
+pub fn check(cfg: &Cfg, program: &Program, nodes: &mut [Node]) {
+    let reachable_from = (0..cfg.blocks.len())
+        .map(|block| cfg.reachable(&[block], false))
+        .collect::<Vec<Vec<bool>>>();
+
+    let mut reported = vec![false; cfg.blocks.len()];
+    for block in 0..cfg.blocks.len() {
+        let in_cycle = cfg
+            .local_successors(block)
+            .any(|successor| reachable_from[successor][block]);
+        if reported[block] || !in_cycle {
+            continue;
+        }
+
+        // Blocks on a cycle with this one
+        let loop_blocks = (0..cfg.blocks.len())
+            .filter(|&other| reachable_from[block][other] && reachable_from[other][block])
+            .collect::<Vec<usize>>();
+        for &other in &loop_blocks {
+            reported[other] = true;
+        }
+
+        let escapes = loop_blocks.iter().any(|&other| {
+            let other = &cfg.blocks[other];
+            other.exit != Exit::Edges
+                || other.successors.iter().any(|edge| match edge.target {
+                    Target::Block(target) => !loop_blocks.contains(&target),
+                    _ => true,
+                })
+                || other.instructions.iter().any(|instruction| {
+                    // JSR, JSRR, TRAP
+                    matches!(instruction.word >> 12, 0b0100 | 0b1111)
+                })
+        });
+        if escapes {
+            continue;
+        }
+
+        // The header is where the loop is entered, or its first block if it is never entered
+        let header = (loop_blocks.iter().copied())
+            .find(|&other| {
+                (cfg.blocks[other].predecessors.iter())
+                    .any(|predecessor| !loop_blocks.contains(predecessor))
+            })
+            .unwrap_or(loop_blocks[0]);
+        let start = cfg.blocks[header].start();
+        warn_once(
+            &mut nodes[cfg.blocks[header].instructions[0].node],
+            format!(
+                "Infinite loop at {}: nothing leaves it, and it does no I/O that could wait for \
+                 input",
+                describe_address(program, start)
+            ),
+        );
+    }
+
+    let Some(entry) = cfg.entry else {
+        return;
+    };
+    let reached = cfg.reachable(&[entry], true);
+    let may_halt = (cfg.blocks.iter().enumerate())
+        .filter(|&(block, _)| reached[block])
+        .any(|(index, block)| {
+            let unresolved_call = block.last().word >> 12 == 0b0100 && cfg.callee(index).is_none();
+            matches!(block.exit, Exit::Halt | Exit::IndirectJump(_)) || unresolved_call
+        });
+    if may_halt {
+        return;
+    }
+
+    let orig = nodes.iter_mut().find(|node| {
+        matches!(
+            node.value,
+            NodeValue::Directive(DirectiveNodeValue::ORIG(_))
+        )
+    });
+    if let Some(orig) = orig {
+        warn_once(
+            orig,
+            "No path from the start of the program reaches `HALT`".to_owned(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::warnings;
+
+    const NEVER_HALTS: &str = "No path from the start of the program reaches `HALT`";
+
+    #[test]
+    fn loops_that_never_end() {
+        let source = ".ORIG x3000\nAND R0, R0, #0\nLOOP ADD R0, R0, #1\nBRnzp LOOP\n.END";
+        assert_eq!(
+            warnings(source, "Infinite loop"),
+            [(
+                3,
+                "Infinite loop at `LOOP`: nothing leaves it, and it does no I/O that could wait \
+                 for input"
+                    .to_owned()
+            )]
+        );
+        assert_eq!(warnings(source, NEVER_HALTS), [(1, NEVER_HALTS.to_owned())]);
+    }
+
+    #[test]
+    fn loops_that_wait_for_input_or_exit() {
+        let source = ".ORIG x3000\nLOOP GETC\nADD R0, R0, #-10\nBRnp LOOP\nHALT\n.END";
+        assert_eq!(warnings(source, "Infinite loop"), []);
+        assert_eq!(warnings(source, NEVER_HALTS), []);
+    }
+
+    #[test]
+    fn unresolved_jumps_may_halt() {
+        let source = ".ORIG x3000\nJMP R2\nHALT\n.END";
+        assert_eq!(warnings(source, NEVER_HALTS), []);
+    }
+
+    #[test]
+    fn follows_resolved_jumps() {
+        let source = ".ORIG x3000\nLEA R2, DONE\nJMP R2\nSPIN BRnzp SPIN\nDONE HALT\n.END";
+        assert_eq!(warnings(source, NEVER_HALTS), []);
+
+        let source = ".ORIG x3000\nLEA R2, SPIN\nJMP R2\nHALT\nSPIN BRnzp SPIN\n.END";
+        assert_eq!(warnings(source, NEVER_HALTS), [(1, NEVER_HALTS.to_owned())]);
+    }
+}
@@ -0,0 +1,202 @@
+//! Warnings for registers that are read before anything has been written to them.
+//!
+//! Registers are tracked as bit sets, with bit `n` standing for `Rn`. A register counts as
+//! written at a point when it is written on every path from the entry to that point. Paths pass
+//! into subroutines through their calls, so a subroutine may read the registers its callers set.
+//! Stores that save a register which is later loaded back from the same place are not reported,
+//! since subroutines save registers their callers may use whether or not they were written.
+
+use std::collections::BTreeMap;
+
+use super::cfg::{Block, Cfg, EdgeKind, Exit, Target};
+use super::{is_restore, is_save};
+use crate::ast::{Node, NodeError};
+use crate::disassembler::disassemble;
+
+const ALL_REGISTERS: u8 = 0xFF;
+
+pub fn check(cfg: &Cfg, nodes: &mut [Node]) {
+    let Some(entry) = cfg.entry else {
+        return;
+    };
+
+    let summaries = written_by_subroutines(cfg, false);
+    let written = propagate(cfg, entry, 0, &summaries, true, false);
+
+    for (block, state) in cfg.blocks.iter().zip(written) {
+        let Some(mut state) = state else {
+            continue;
+        };
+
+        for instruction in &block.instructions {
+            let node = &mut nodes[instruction.node];
+            for (register, operand) in instruction.reads() {
+                let saved = operand == Some(0) && is_save(cfg, instruction);
+                if state & 1 << register != 0 || saved {
+                    continue;
+                }
+
+                let location = operand.and_then(|operand| node.operand_locs.get(operand));
+                let warning = match location {
+                    Some(&(start_loc, end_loc)) => NodeError::WarningAt(
+                        start_loc,
+                        end_loc,
+                        format!("`R{}` may be read before it has been written", register),
+                    ),
+                    None => {
+                        let instruction = disassemble(instruction.word, instruction.address);
+                        NodeError::Warning(format!(
+                            "`{}` reads `R{}`, which may not have been written",
+                            instruction, register
+                        ))
+                    }
+                };
+                node.errors.push(warning);
+                // Only the first read is reported
+                state |= 1 << register;
+            }
+
+            for register in instruction.writes() {
+                state |= 1 << register;
+            }
+        }
+    }
+}
+
+/// Registers written on every path through each subroutine, keyed by its entry block. With
+/// `undo_restores` set, loading a register back from where it was saved undoes its earlier
+/// writes, so what is left are the registers each subroutine changes for its caller.
+pub(super) fn written_by_subroutines(cfg: &Cfg, undo_restores: bool) -> BTreeMap<usize, u8> {
+    let mut summaries = (cfg.subroutines.iter())
+        .map(|&subroutine| (subroutine, ALL_REGISTERS))
+        .collect::<BTreeMap<usize, u8>>();
+
+    // Summaries start out assuming everything is written and shrink until they settle, which
+    // handles subroutines that call each other
+    loop {
+        let mut changed = false;
+        for &subroutine in &cfg.subroutines {
+            let written = propagate(cfg, subroutine, 0, &summaries, false, undo_restores);
+            let summary = (cfg.blocks.iter().zip(written))
+                .filter(|(block, _)| block.exit == Exit::Return)
+                .filter_map(|(block, state)| Some(written_after(cfg, block, state?, undo_restores)))
+                .fold(ALL_REGISTERS, |summary, state| summary & state);
+
+            changed |= summaries.insert(subroutine, summary) != Some(summary);
+        }
+
+        if !changed {
+            return summaries;
+        }
+    }
+}
+
+/// Registers written at the start of every block reachable from the start block, or `None` for
+/// blocks it does not reach. Calls are entered when `follow_calls` is set, and are otherwise
+/// assumed to write what the summary of the subroutine says.
+pub(super) fn propagate(
+    cfg: &Cfg,
+    start: usize,
+    initial: u8,
+    summaries: &BTreeMap<usize, u8>,
+    follow_calls: bool,
+    undo_restores: bool,
+) -> Vec<Option<u8>> {
+    let mut written = vec![None; cfg.blocks.len()];
+    written[start] = Some(initial);
+    let mut pending = vec![start];
+
+    while let Some(block) = pending.pop() {
+        let state = written_after(
+            cfg,
+            &cfg.blocks[block],
+            written[block].unwrap(),
+            undo_restores,
+        );
+        let callee = cfg.callee(block);
+
+        for edge in &cfg.blocks[block].successors {
+            let Target::Block(successor) = edge.target else {
+                continue;
+            };
+
+            let state = match edge.kind {
+                EdgeKind::Call if !follow_calls => continue,
+                EdgeKind::CallReturn => {
+                    state
+                        | callee
+                            .and_then(|callee| summaries.get(&callee))
+                            .unwrap_or(&0)
+                }
+                _ => state,
+            };
+
+            let merged = written[successor].map_or(state, |written| written & state);
+            if written[successor] != Some(merged) {
+                written[successor] = Some(merged);
+                pending.push(successor);
+            }
+        }
+    }
+
+    written
+}
+
+/// Registers written by the end of the block, given those written at its start.
+fn written_after(cfg: &Cfg, block: &Block, state: u8, undo_restores: bool) -> u8 {
+    block.instructions.iter().fold(state, |state, instruction| {
+        let restored = undo_restores && is_restore(cfg, instruction);
+        (instruction.writes().into_iter()).fold(state, |state, register| match restored {
+            true => state & !(1 << register),
+            false => state | 1 << register,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::warnings;
+
+    #[test]
+    fn reports_registers_read_before_being_written() {
+        let source = "
+            .ORIG x3000
+                    AND R0, R0, #0
+                    ADD R0, R0, R1
+                    ADD R1, R1, #1
+                    BRp DONE
+                    ADD R2, R2, #1
+            DONE    ADD R3, R2, #0
+                    HALT
+            .END
+        ";
+
+        assert_eq!(
+            warnings(source, "before it has been written"),
+            [
+                (4, "`R1` may be read before it has been written".to_owned()),
+                (7, "`R2` may be read before it has been written".to_owned()),
+                (8, "`R2` may be read before it has been written".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn saving_a_register_does_not_read_it() {
+        let source = "
+            .ORIG x3000
+                    AND R1, R1, #0
+                    JSR SUB
+                    ADD R1, R1, #1
+                    HALT
+            SUB     ST R2, SAVE_R2
+                    ADD R2, R1, #1
+                    LD R2, SAVE_R2
+                    RET
+            SAVE_R2 .BLKW #1
+            .END
+        ";
+
+        assert_eq!(warnings(source, "written"), []);
+    }
+}
@@ -0,0 +1,519 @@
+//! Assembler that lays out `.ORIG`/`.END` sections and encodes them into object segments.
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::ast::{
+    AddAndOpcodeInstructionNodeValue, DirectiveNodeValue, Expression, InstructionNodeValue,
+    LiteralOrLabel, Node, NodeError, NodeValue,
+};
+use crate::tokens::NumberLiteralTokenValue;
+use crate::{lexer, parser, passes};
+
+#[derive(Debug)]
+pub struct Program {
+    pub segments: Vec<Segment>,
+    pub symbols: HashMap<String, u16>,
+    /// Maps every assembled address to the index of the node that produced it.
+    pub source_map: BTreeMap<u16, usize>,
+}
+
+impl Program {
+    /// Word assembled at the address, if any segment covers it.
+    pub fn word_at(&self, address: u16) -> Option<u16> {
+        self.segments.iter().find_map(|segment| {
+            let offset = address.wrapping_sub(segment.origin) as usize;
+            segment.words.get(offset).copied()
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Segment {
+    pub origin: u16,
+    pub words: Vec<u16>,
+}
+
+impl Segment {
+    /// One past the last address occupied by the segment.
+    pub fn end(&self) -> u32 {
+        self.origin as u32 + self.words.len() as u32
+    }
+}
+
+/// A `.ORIG`/`.END` block and the addresses assigned to its nodes.
+struct Section {
+    orig_node: usize,
+    origin: u16,
+    placements: Vec<(usize, u16)>,
+    length: u32,
+    overflowed: bool,
+}
+
+pub fn assemble(ast: &mut [Node]) -> Program {
+    let (sections, symbols) = layout_sections(ast);
+    verify_sections_do_not_overlap(ast, &sections);
+
+    let mut segments = Vec::<Segment>::new();
+    let mut source_map = BTreeMap::<u16, usize>::new();
+
+    for section in &sections {
+        let mut words = vec![0; section.length.min(0x10000 - section.origin as u32) as usize];
+
+        for &(idx, address) in &section.placements {
+            let encoded = encode_node(&mut ast[idx], address, &symbols);
+            let offset = (address - section.origin) as usize;
+
+            for (i, word) in encoded.into_iter().enumerate() {
+                words[offset + i] = word;
+                source_map.insert(address + i as u16, idx);
+            }
+        }
+
+        segments.push(Segment {
+            origin: section.origin,
+            words,
+        });
+    }
+
+    Program {
+        segments,
+        symbols,
+        source_map,
+    }
+}
+
+/// Lexes, parses, checks and assembles the source. Returns the errors formatted as
+/// `line:col: message` if it does not assemble.
+pub fn assemble_source(source: &str) -> Result<(Program, Vec<Node>), Vec<String>> {
+    let tokens = lexer::analyze(source);
+    let mut nodes = parser::parse_ast(&tokens);
+    passes::verify_labels(&mut nodes);
+    passes::verify_number_literals_within_range(&mut nodes);
+    let program = assemble(&mut nodes);
+
+    let errors = nodes
+        .iter()
+        .flat_map(|node| {
+            node.errors.iter().filter_map(move |error| match error {
+                NodeError::Error(error) => Some(format!(
+                    "{}:{}: {}",
+                    node.start_loc.line, node.start_loc.col, error
+                )),
+                NodeError::Warning(_) | NodeError::WarningAt(..) => None,
+            })
+        })
+        .collect::<Vec<String>>();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok((program, nodes))
+}
+
+fn layout_sections(ast: &mut [Node]) -> (Vec<Section>, HashMap<String, u16>) {
+    let mut sections = Vec::<Section>::new();
+    let mut symbols = HashMap::<String, u16>::new();
+    let mut current: Option<Section> = None;
+
+    let defined_labels = ast
+        .iter()
+        .filter_map(|node| match &node.value {
+            NodeValue::Label(label) => Some(label.clone()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    for idx in 0..ast.len() {
+        match ast[idx].value.clone() {
+            NodeValue::Directive(DirectiveNodeValue::ORIG(literal)) => {
+                if let Some(section) = current.take() {
+                    ast[section.orig_node].errors.push(NodeError::Error(
+                        "Missing `.END` before the next `.ORIG`".to_string(),
+                    ));
+                    sections.push(section);
+                }
+
+                match literal.to_isize() {
+                    Ok(origin) if (0..=0xFFFF).contains(&origin) => {
+                        current = Some(Section {
+                            orig_node: idx,
+                            origin: origin as u16,
+                            placements: Vec::new(),
+                            length: 0,
+                            overflowed: false,
+                        })
+                    }
+                    _ => ast[idx].errors.push(NodeError::Error(
+                        "`.ORIG` address must be within [x0000, xFFFF]".to_string(),
+                    )),
+                }
+            }
+            NodeValue::Directive(DirectiveNodeValue::END) => match current.take() {
+                Some(section) => sections.push(section),
+                None => ast[idx].errors.push(NodeError::Error(
+                    "`.END` without a matching `.ORIG`".to_string(),
+                )),
+            },
+            NodeValue::Label(label) => {
+                let Some(section) = &current else {
+                    ast[idx].errors.push(NodeError::Error(
+                        "Label is outside of a `.ORIG`/`.END` section".to_string(),
+                    ));
+                    continue;
+                };
+
+                let address = section.origin as u32 + section.length;
+                match symbols.entry(label) {
+                    Entry::Occupied(entry) => ast[idx].errors.push(NodeError::Error(format!(
+                        "Label `{}` is already defined",
+                        entry.key()
+                    ))),
+                    Entry::Vacant(entry) if address > 0xFFFF => {
+                        ast[idx].errors.push(NodeError::Error(format!(
+                            "Label `{}` is past xFFFF, the end of memory",
+                            entry.key()
+                        )))
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(address as u16);
+                    }
+                }
+            }
+            value => {
+                let size = node_size(&mut ast[idx], &value, &symbols, &defined_labels);
+                if size == 0 {
+                    continue;
+                }
+
+                let Some(section) = &mut current else {
+                    ast[idx].errors.push(NodeError::Error(
+                        "Code and data must be inside a `.ORIG`/`.END` section".to_string(),
+                    ));
+                    continue;
+                };
+
+                let address = section.origin as u32 + section.length;
+                if address + size > 0x10000 {
+                    if !section.overflowed {
+                        section.overflowed = true;
+                        ast[idx].errors.push(NodeError::Error(format!(
+                            "Section starting at x{:04X} extends past xFFFF",
+                            section.origin
+                        )));
+                    }
+                } else {
+                    section.placements.push((idx, address as u16));
+                }
+
+                section.length += size;
+            }
+        }
+    }
+
+    if let Some(section) = current {
+        ast[section.orig_node].errors.push(NodeError::Error(
+            "Missing `.END` for this `.ORIG`".to_string(),
+        ));
+        sections.push(section);
+    }
+
+    (sections, symbols)
+}
+
+/// Number of words a node occupies in memory. `.BLKW` sizes may only use labels defined above
+/// them, since later labels do not have addresses yet.
+fn node_size(
+    node: &mut Node,
+    value: &NodeValue,
+    symbols: &HashMap<String, u16>,
+    defined_labels: &HashSet<String>,
+) -> u32 {
+    match value {
+        NodeValue::Instruction(_) => 1,
+        NodeValue::Directive(DirectiveNodeValue::FILL(_)) => 1,
+        NodeValue::Directive(DirectiveNodeValue::BLKW(expression)) => {
+            match evaluate(expression, symbols) {
+                Ok(size) if (1..=0x10000).contains(&size) => size as u32,
+                Ok(_) => {
+                    node.errors.push(NodeError::Error(
+                        "`.BLKW` size must be within [#1, #65536]".to_string(),
+                    ));
+                    0
+                }
+                Err(label) => {
+                    // Undefined labels are reported by `passes::verify_labels`
+                    if defined_labels.contains(&label) {
+                        node.errors.push(NodeError::Error(format!(
+                            "Label `{}` must be defined before the `.BLKW` that uses it",
+                            label
+                        )));
+                    }
+                    0
+                }
+            }
+        }
+        NodeValue::Directive(DirectiveNodeValue::STRINGZ(string)) => {
+            string.chars().count() as u32 + 1
+        }
+        _ => 0,
+    }
+}
+
+fn verify_sections_do_not_overlap(ast: &mut [Node], sections: &[Section]) {
+    for (i, later) in sections.iter().enumerate() {
+        let later_end = later.origin as u32 + later.length;
+
+        for earlier in &sections[..i] {
+            let earlier_end = earlier.origin as u32 + earlier.length;
+
+            if later.length > 0
+                && earlier.length > 0
+                && (later.origin as u32) < earlier_end
+                && (earlier.origin as u32) < later_end
+            {
+                let line = ast[earlier.orig_node].start_loc.line;
+                ast[later.orig_node].errors.push(NodeError::Error(format!(
+                    "Section x{:04X}-x{:04X} overlaps section x{:04X}-x{:04X} (line {})",
+                    later.origin,
+                    later_end - 1,
+                    earlier.origin,
+                    earlier_end - 1,
+                    line
+                )));
+            }
+        }
+    }
+}
+
+fn encode_node(node: &mut Node, address: u16, symbols: &HashMap<String, u16>) -> Vec<u16> {
+    match node.value.clone() {
+        NodeValue::Instruction(instruction) => {
+            match encode_instruction(&instruction, address, symbols) {
+                Ok(word) => vec![word],
+                Err(msg) => {
+                    node.errors.push(NodeError::Error(msg));
+                    vec![0]
+                }
+            }
+        }
+        NodeValue::Directive(DirectiveNodeValue::FILL(expression)) => {
+            let value = evaluate(&expression, symbols).unwrap_or(0);
+            if !(-0x8000..=0xFFFF).contains(&value) {
+                node.errors.push(NodeError::Warning(
+                    "`.FILL` value does not fit in 16 bits".to_string(),
+                ));
+            }
+            vec![value as u16]
+        }
+        NodeValue::Directive(DirectiveNodeValue::BLKW(expression)) => {
+            vec![0; evaluate(&expression, symbols).unwrap_or(0) as usize]
+        }
+        NodeValue::Directive(DirectiveNodeValue::STRINGZ(string)) => string
+            .chars()
+            .map(|c| c as u16)
+            .chain(std::iter::once(0))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn encode_instruction(
+    instruction: &InstructionNodeValue,
+    address: u16,
+    symbols: &HashMap<String, u16>,
+) -> Result<u16, String> {
+    let word = match instruction {
+        InstructionNodeValue::ADD(value) => 0x1000 | encode_add_and_operands(value),
+        InstructionNodeValue::AND(value) => 0x5000 | encode_add_and_operands(value),
+        InstructionNodeValue::BR {
+            n,
+            z,
+            p,
+            pc_offset9,
+        } => {
+            (*n as u16) << 11
+                | (*z as u16) << 10
+                | (*p as u16) << 9
+                | resolve_offset(pc_offset9, address, 9, symbols)?
+        }
+        InstructionNodeValue::JMP { base_r } => 0xC000 | (*base_r as u16) << 6,
+        InstructionNodeValue::JSR { pc_offset11 } => {
+            0x4800 | resolve_offset(pc_offset11, address, 11, symbols)?
+        }
+        InstructionNodeValue::JSRR { base_r } => 0x4000 | (*base_r as u16) << 6,
+        InstructionNodeValue::LD { dr, pc_offset9 } => {
+            0x2000 | (*dr as u16) << 9 | resolve_offset(pc_offset9, address, 9, symbols)?
+        }
+        InstructionNodeValue::LDI { dr, pc_offset9 } => {
+            0xA000 | (*dr as u16) << 9 | resolve_offset(pc_offset9, address, 9, symbols)?
+        }
+        InstructionNodeValue::LDR {
+            dr,
+            base_r,
+            offset6,
+        } => {
+            0x6000
+                | (*dr as u16) << 9
+                | (*base_r as u16) << 6
+                | resolve_offset(offset6, address, 6, symbols)?
+        }
+        InstructionNodeValue::LEA { dr, pc_offset9 } => {
+            0xE000 | (*dr as u16) << 9 | resolve_offset(pc_offset9, address, 9, symbols)?
+        }
+        InstructionNodeValue::NOT { dr, sr } => 0x903F | (*dr as u16) << 9 | (*sr as u16) << 6,
+        InstructionNodeValue::RET => 0xC1C0,
+        InstructionNodeValue::RTI => 0x8000,
+        InstructionNodeValue::ST { sr, pc_offset9 } => {
+            0x3000 | (*sr as u16) << 9 | resolve_offset(pc_offset9, address, 9, symbols)?
+        }
+        InstructionNodeValue::STI { sr, pc_offset9 } => {
+            0xB000 | (*sr as u16) << 9 | resolve_offset(pc_offset9, address, 9, symbols)?
+        }
+        InstructionNodeValue::STR {
+            sr,
+            base_r,
+            offset6,
+        } => {
+            0x7000
+                | (*sr as u16) << 9
+                | (*base_r as u16) << 6
+                | resolve_offset(offset6, address, 6, symbols)?
+        }
+        InstructionNodeValue::TRAP { trapvect8 } => 0xF000 | literal_bits(trapvect8, 8),
+        InstructionNodeValue::GETC
+        | InstructionNodeValue::OUT
+        | InstructionNodeValue::PUTS
+        | InstructionNodeValue::IN
+        | InstructionNodeValue::PUTSP
+        | InstructionNodeValue::HALT => 0xF000 | instruction.trap_vector().unwrap_or(0) as u16,
+        InstructionNodeValue::Error { .. } => 0,
+    };
+
+    Ok(word)
+}
+
+/// Evaluates a constant expression, failing with the first label that has no address.
+fn evaluate(expression: &Expression, symbols: &HashMap<String, u16>) -> Result<isize, String> {
+    match expression {
+        Expression::Operand(LiteralOrLabel::Literal(literal)) => {
+            Ok(literal.to_isize().unwrap_or(0))
+        }
+        Expression::Operand(LiteralOrLabel::Label(label)) => symbols
+            .get(label)
+            .map(|&address| address as isize)
+            .ok_or_else(|| label.clone()),
+        Expression::Add(lhs, rhs) => Ok(evaluate(lhs, symbols)? + evaluate(rhs, symbols)?),
+        Expression::Subtract(lhs, rhs) => Ok(evaluate(lhs, symbols)? - evaluate(rhs, symbols)?),
+    }
+}
+
+fn encode_add_and_operands(value: &AddAndOpcodeInstructionNodeValue) -> u16 {
+    match value {
+        AddAndOpcodeInstructionNodeValue::SR2 { dr, sr1, sr2 } => {
+            (*dr as u16) << 9 | (*sr1 as u16) << 6 | *sr2 as u16
+        }
+        AddAndOpcodeInstructionNodeValue::IMM { dr, sr1, imm5 } => {
+            (*dr as u16) << 9 | (*sr1 as u16) << 6 | 0x20 | literal_bits(imm5, 5)
+        }
+    }
+}
+
+/// Resolves an offset operand, turning labels into offsets relative to the incremented PC.
+fn resolve_offset(
+    operand: &LiteralOrLabel,
+    address: u16,
+    bits: u32,
+    symbols: &HashMap<String, u16>,
+) -> Result<u16, String> {
+    match operand {
+        LiteralOrLabel::Literal(literal) => Ok(literal_bits(literal, bits)),
+        LiteralOrLabel::Label(label) => {
+            // Undefined labels are reported by `passes::verify_labels`
+            let Some(&target) = symbols.get(label) else {
+                return Ok(0);
+            };
+
+            let offset = target as isize - (address as isize + 1);
+            let limit = 1 << (bits - 1);
+            if offset < -limit || offset >= limit {
+                return Err(format!(
+                    "Label `{}` is too far away ({} words, must be within [{}, {}] for a {} bit offset)",
+                    label,
+                    offset,
+                    -limit,
+                    limit - 1,
+                    bits
+                ));
+            }
+
+            Ok(offset as u16 & ((1 << bits) - 1))
+        }
+    }
+}
+
+/// Truncates a literal to its lowest `bits` bits. Out of range literals are reported by
+/// `passes::verify_number_literals_within_range`.
+fn literal_bits(literal: &NumberLiteralTokenValue, bits: u32) -> u16 {
+    literal.to_isize().unwrap_or(0) as u16 & ((1 << bits) - 1) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(source: &str) -> Vec<u16> {
+        let (program, _) = assemble_source(source).unwrap();
+        program.segments[0].words.clone()
+    }
+
+    #[test]
+    fn encodes_operate_instructions() {
+        assert_eq!(
+            words(".ORIG x3000\nADD R1, R2, R3\nADD R1, R2, #-1\nAND R0, R0, #0\nNOT R4, R5\n.END"),
+            [0x1283, 0x12BF, 0x5020, 0x997F]
+        );
+    }
+
+    #[test]
+    fn encodes_pc_relative_offsets() {
+        let source = "
+            .ORIG x3000
+            LOOP LD R0, DATA
+            BRnp LOOP
+            LEA R1, DATA
+            ST R2, DATA
+            JSR LOOP
+            DATA .FILL #0
+            .END
+        ";
+        assert_eq!(
+            words(source),
+            [0x2004, 0x0BFE, 0xE202, 0x3401, 0x4FFB, 0x0000]
+        );
+    }
+
+    #[test]
+    fn encodes_base_offset_and_control_instructions() {
+        let source = ".ORIG x3000\nLDR R0, R6, #-1\nSTR R7, R6, #0\nJMP R3\nRET\nJSRR R4\nRTI\nTRAP x25\nHALT\n.END";
+        assert_eq!(
+            words(source),
+            [0x61BF, 0x7F80, 0xC0C0, 0xC1C0, 0x4100, 0x8000, 0xF025, 0xF025]
+        );
+    }
+
+    #[test]
+    fn encodes_data_directives() {
+        assert_eq!(
+            words(".ORIG x3000\nA .FILL A+2\n.BLKW #2\n.STRINGZ \"hi\\n\"\n.END"),
+            [0x3002, 0, 0, 0x68, 0x69, 0x0A, 0]
+        );
+    }
+
+    #[test]
+    fn reports_labels_past_the_end_of_memory() {
+        let errors = assemble_source(".ORIG xFFFF\n.FILL #1\nEND\n.END").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("Label `END` is past xFFFF"));
+    }
+}
@@ -2,7 +2,7 @@
 
 use crate::tokens::{
     DirectiveTokenValue, FileLoc, NumberLiteralTokenValue, OpcodeTokenValue, RegisterTokenValue,
-    Token, TrapRoutineTokenValue,
+    Token,
 };
 
 #[derive(Debug)]
@@ -11,12 +11,36 @@ pub struct Node {
     pub start_loc: FileLoc,
     pub end_loc: FileLoc,
     pub errors: Vec<NodeError>,
+    /// Start and end of each operand of an instruction, in order.
+    pub operand_locs: Vec<(FileLoc, FileLoc)>,
+}
+
+impl Node {
+    pub fn new(value: NodeValue, start_loc: FileLoc, end_loc: FileLoc) -> Self {
+        Node {
+            value,
+            start_loc,
+            end_loc,
+            errors: Vec::new(),
+            operand_locs: Vec::new(),
+        }
+    }
+
+    /// Creates a node with an error, e.g. for tokens that could not be parsed.
+    pub fn error(value: NodeValue, start_loc: FileLoc, end_loc: FileLoc, message: String) -> Self {
+        Node {
+            errors: vec![NodeError::Error(message)],
+            ..Node::new(value, start_loc, end_loc)
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum NodeError {
     Error(String),
     Warning(String),
+    /// Warning about part of the node, such as one of its operands, between the locations.
+    WarningAt(FileLoc, FileLoc, String),
 }
 
 #[derive(Debug, Clone)]
@@ -26,7 +50,6 @@ pub enum NodeValue {
     Label(String),
     Instruction(InstructionNodeValue),
     Directive(DirectiveNodeValue),
-    TrapRoutine(TrapRoutineTokenValue),
     UnexpectedToken(Token),
 }
 
@@ -36,6 +59,14 @@ pub enum LiteralOrLabel {
     Label(String),
 }
 
+/// Constant expression built from literals and labels, e.g. `LABEL+#1` or `x4000-#2`.
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Operand(LiteralOrLabel),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+}
+
 #[derive(Debug, Clone)]
 pub enum InstructionNodeValue {
     ADD(AddAndOpcodeInstructionNodeValue),
@@ -52,7 +83,9 @@ pub enum InstructionNodeValue {
     JSR {
         pc_offset11: LiteralOrLabel,
     },
-    // JSRR,
+    JSRR {
+        base_r: RegisterTokenValue,
+    },
     LD {
         dr: RegisterTokenValue,
         pc_offset9: LiteralOrLabel,
@@ -75,7 +108,7 @@ pub enum InstructionNodeValue {
         sr: RegisterTokenValue,
     },
     RET,
-    // RTI,
+    RTI,
     ST {
         sr: RegisterTokenValue,
         pc_offset9: LiteralOrLabel,
@@ -92,12 +125,33 @@ pub enum InstructionNodeValue {
     TRAP {
         trapvect8: NumberLiteralTokenValue,
     },
+    GETC,
+    OUT,
+    PUTS,
+    IN,
+    PUTSP,
+    HALT,
     Error {
         opcode: OpcodeTokenValue,
         args: Option<Vec<Token>>,
     },
 }
 
+impl InstructionNodeValue {
+    /// Trap vector of the trap routine aliases, e.g. x25 for `HALT`.
+    pub fn trap_vector(&self) -> Option<u8> {
+        match self {
+            InstructionNodeValue::GETC => Some(0x20),
+            InstructionNodeValue::OUT => Some(0x21),
+            InstructionNodeValue::PUTS => Some(0x22),
+            InstructionNodeValue::IN => Some(0x23),
+            InstructionNodeValue::PUTSP => Some(0x24),
+            InstructionNodeValue::HALT => Some(0x25),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum AddAndOpcodeInstructionNodeValue {
     SR2 {
@@ -115,8 +169,8 @@ pub enum AddAndOpcodeInstructionNodeValue {
 #[derive(Debug, Clone)]
 pub enum DirectiveNodeValue {
     ORIG(NumberLiteralTokenValue),
-    FILL(NumberLiteralTokenValue),
-    BLKW(NumberLiteralTokenValue),
+    FILL(Expression),
+    BLKW(Expression),
     STRINGZ(String),
     END,
     Error {
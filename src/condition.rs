@@ -0,0 +1,336 @@
+//! Breakpoint conditions such as `R6 < x2F00` or `PC == POP && R5 != 0`.
+//!
+//! Operands are registers, `PC`, `PSR`, labels, number literals and memory words written as
+//! `[address]`. Values are 16-bit words compared as unsigned numbers, and a condition holds when
+//! it evaluates to a non-zero word.
+
+use std::collections::HashMap;
+
+use crate::simulator::Machine;
+
+#[derive(Debug, Clone)]
+pub struct Condition {
+    pub text: String,
+    expression: Expression,
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+    Word(u16),
+    Register(usize),
+    PC,
+    PSR,
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+}
+
+/// Operators from lowest to highest precedence.
+const PRECEDENCE: [&[(&str, Operator)]; 4] = [
+    &[("||", Operator::Or)],
+    &[("&&", Operator::And)],
+    &[
+        ("==", Operator::Equal),
+        ("!=", Operator::NotEqual),
+        ("<=", Operator::LessEqual),
+        (">=", Operator::GreaterEqual),
+        ("<", Operator::Less),
+        (">", Operator::Greater),
+    ],
+    &[("+", Operator::Add), ("-", Operator::Subtract)],
+];
+
+impl Condition {
+    /// Parses the condition, resolving labels through the symbol table.
+    pub fn parse(text: &str, symbols: &HashMap<String, u16>) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            symbols,
+        };
+
+        let expression = parser.parse_binary(0)?;
+        if let Some(token) = tokens.get(parser.position) {
+            return Err(format!("Unexpected `{}` in condition", token));
+        }
+
+        Ok(Condition {
+            text: text.trim().to_owned(),
+            expression,
+        })
+    }
+
+    pub fn holds(&self, machine: &Machine) -> bool {
+        evaluate(&self.expression, machine) != 0
+    }
+}
+
+/// Parses a label or a number literal such as `x3000`, `#10`, `#-1` or `10`.
+pub fn parse_word(text: &str, symbols: &HashMap<String, u16>) -> Option<u16> {
+    if let Some(&address) = symbols.get(text) {
+        return Some(address);
+    }
+
+    let (digits, radix) = match text.as_bytes() {
+        [b'0', b'x' | b'X', ..] => (&text[2..], 16),
+        [b'x' | b'X', ..] => (&text[1..], 16),
+        [b'b' | b'B', ..] => (&text[1..], 2),
+        [b'#', ..] => (&text[1..], 10),
+        _ => (text, 10),
+    };
+    let value = i32::from_str_radix(digits, radix).ok()?;
+    (-0x8000..=0xFFFF).contains(&value).then_some(value as u16)
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_alphanumeric() || c == '_' || c == '#' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                let negative_decimal = c == '-' && word == "#";
+                if !(c.is_alphanumeric() || c == '_' || c == '#' || negative_decimal) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        } else {
+            chars.next();
+            let pair = chars.peek().map(|&next| format!("{}{}", c, next));
+            match pair.as_deref() {
+                Some("==" | "!=" | "<=" | ">=" | "&&" | "||") => {
+                    tokens.push(pair.unwrap());
+                    chars.next();
+                }
+                _ if "<>!()[]+-".contains(c) => tokens.push(c.to_string()),
+                _ => return Err(format!("Unexpected `{}` in condition", c)),
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+    symbols: &'a HashMap<String, u16>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&str, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or("Condition ended unexpectedly")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("Expected `{}` but found `{}`", expected, token)),
+        }
+    }
+
+    /// Parses operators at the precedence level and above, all of which are left associative.
+    fn parse_binary(&mut self, level: usize) -> Result<Expression, String> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.parse_operand();
+        };
+
+        let mut left = self.parse_binary(level + 1)?;
+        while let Some(&(_, operator)) = operators
+            .iter()
+            .find(|(symbol, _)| self.peek() == Some(symbol))
+        {
+            self.position += 1;
+            let right = self.parse_binary(level + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_operand(&mut self) -> Result<Expression, String> {
+        let token = self.next()?.to_owned();
+        let expression = match token.to_uppercase().as_str() {
+            "(" => {
+                let expression = self.parse_binary(0)?;
+                self.expect(")")?;
+                expression
+            }
+            "[" => {
+                let address = self.parse_binary(0)?;
+                self.expect("]")?;
+                Expression::Memory(Box::new(address))
+            }
+            "!" => Expression::Not(Box::new(self.parse_operand()?)),
+            "-" => Expression::Binary(
+                Operator::Subtract,
+                Box::new(Expression::Word(0)),
+                Box::new(self.parse_operand()?),
+            ),
+            "PC" => Expression::PC,
+            "PSR" => Expression::PSR,
+            register @ ("R0" | "R1" | "R2" | "R3" | "R4" | "R5" | "R6" | "R7") => {
+                Expression::Register((register.as_bytes()[1] - b'0') as usize)
+            }
+            _ => Expression::Word(
+                parse_word(&token, self.symbols)
+                    .ok_or(format!("Unknown label or invalid number `{}`", token))?,
+            ),
+        };
+
+        Ok(expression)
+    }
+}
+
+fn evaluate(expression: &Expression, machine: &Machine) -> u16 {
+    match expression {
+        Expression::Word(value) => *value,
+        Expression::Register(register) => machine.registers[*register],
+        Expression::PC => machine.pc,
+        Expression::PSR => machine.psr,
+        Expression::Memory(address) => machine.read_memory(evaluate(address, machine)),
+        Expression::Not(operand) => (evaluate(operand, machine) == 0) as u16,
+        Expression::Binary(operator, left, right) => {
+            let left = evaluate(left, machine);
+            let right = evaluate(right, machine);
+            match operator {
+                Operator::Or => (left != 0 || right != 0) as u16,
+                Operator::And => (left != 0 && right != 0) as u16,
+                Operator::Equal => (left == right) as u16,
+                Operator::NotEqual => (left != right) as u16,
+                Operator::Less => (left < right) as u16,
+                Operator::LessEqual => (left <= right) as u16,
+                Operator::Greater => (left > right) as u16,
+                Operator::GreaterEqual => (left >= right) as u16,
+                Operator::Add => left.wrapping_add(right),
+                Operator::Subtract => left.wrapping_sub(right),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols() -> HashMap<String, u16> {
+        HashMap::from([("POP".to_owned(), 0x3010)])
+    }
+
+    fn machine() -> Machine {
+        let mut machine = Machine::new();
+        machine.registers[5] = 3;
+        machine.registers[6] = 0x2F00;
+        machine.pc = 0x3010;
+        machine.memory[0x4000] = 0x1234;
+        machine
+    }
+
+    fn holds(text: &str) -> bool {
+        Condition::parse(text, &symbols())
+            .unwrap()
+            .holds(&machine())
+    }
+
+    fn parse_error(text: &str) -> String {
+        Condition::parse(text, &symbols()).unwrap_err()
+    }
+
+    #[test]
+    fn parses_words() {
+        let symbols = symbols();
+        assert_eq!(parse_word("x3000", &symbols), Some(0x3000));
+        assert_eq!(parse_word("X3000", &symbols), Some(0x3000));
+        assert_eq!(parse_word("0x3000", &symbols), Some(0x3000));
+        assert_eq!(parse_word("#10", &symbols), Some(10));
+        assert_eq!(parse_word("#-1", &symbols), Some(0xFFFF));
+        assert_eq!(parse_word("10", &symbols), Some(10));
+        assert_eq!(parse_word("b101", &symbols), Some(5));
+        assert_eq!(parse_word("POP", &symbols), Some(0x3010));
+        assert_eq!(parse_word("x10000", &symbols), None);
+        assert_eq!(parse_word("PUSH", &symbols), None);
+    }
+
+    #[test]
+    fn evaluates_operands() {
+        assert!(holds("R6 == x2F00"));
+        assert!(holds("r5 == #3"));
+        assert!(holds("PC == POP"));
+        assert!(holds("PSR == 2"));
+        assert!(holds("[x4000] == x1234"));
+        assert!(holds("[x3FFF + 1] == x1234"));
+        assert!(holds("R5"));
+        assert!(!holds("R0"));
+        assert!(holds("!R0"));
+        assert!(holds("-R5 == #-3"));
+    }
+
+    #[test]
+    fn evaluates_operators() {
+        assert!(holds("R5 != 4"));
+        assert!(holds("R5 < 4") && !holds("R5 < 3"));
+        assert!(holds("R5 <= 3") && !holds("R5 <= 2"));
+        assert!(holds("R5 > 2") && !holds("R5 > 3"));
+        assert!(holds("R5 >= 3") && !holds("R5 >= 4"));
+        assert!(holds("R5 + 1 == 4"));
+        assert!(holds("R5 - 4 == xFFFF"));
+        // Comparisons are unsigned
+        assert!(holds("R5 - 4 > R5"));
+    }
+
+    #[test]
+    fn combines_with_precedence() {
+        assert!(holds("PC == POP && R5 != 0"));
+        assert!(!holds("PC == POP && R5 == 0"));
+        assert!(holds("R5 == 0 || R6 == x2F00"));
+        assert!(holds("R0 == 1 && R5 == 0 || R5 == 3"));
+        assert!(!holds("R0 == 1 && (R5 == 0 || R5 == 3)"));
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        assert_eq!(parse_error("R5 =="), "Condition ended unexpectedly");
+        assert_eq!(parse_error("R5 == 3)"), "Unexpected `)` in condition");
+        assert_eq!(parse_error("R5 = 3"), "Unexpected `=` in condition");
+        assert_eq!(parse_error("(R5 == 3"), "Condition ended unexpectedly");
+        assert_eq!(parse_error("[R5 == 3)"), "Expected `]` but found `)`");
+        assert_eq!(
+            parse_error("PUSH == 3"),
+            "Unknown label or invalid number `PUSH`"
+        );
+        assert_eq!(
+            parse_error("R5 == xZZ"),
+            "Unknown label or invalid number `xZZ`"
+        );
+    }
+}
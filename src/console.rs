@@ -0,0 +1,101 @@
+//! Character I/O used by the simulator's trap routines.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+pub trait Console {
+    /// Reads the next character, or `None` once the input is exhausted.
+    fn read_char(&mut self) -> Option<u8>;
+    /// Reads the next character if one is available without waiting, e.g. to raise keyboard
+    /// interrupts.
+    fn poll_char(&mut self) -> Option<u8> {
+        self.read_char()
+    }
+    fn write_char(&mut self, c: u8);
+}
+
+/// Console connected to the process's stdin and stdout. Stdin is read on a separate thread so
+/// it can be polled without blocking.
+pub struct StdConsole {
+    input: Receiver<u8>,
+}
+
+impl StdConsole {
+    pub fn new() -> Self {
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0];
+            while let Ok(1) = io::stdin().read(&mut buf) {
+                if sender.send(buf[0]).is_err() {
+                    break;
+                }
+            }
+        });
+
+        StdConsole { input }
+    }
+}
+
+impl Default for StdConsole {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Console for StdConsole {
+    fn read_char(&mut self) -> Option<u8> {
+        self.input.recv().ok()
+    }
+
+    fn poll_char(&mut self) -> Option<u8> {
+        self.input.try_recv().ok()
+    }
+
+    fn write_char(&mut self, c: u8) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&[c]);
+        let _ = stdout.flush();
+    }
+}
+
+/// Console with scripted input that captures everything written to it.
+#[derive(Debug, Default, Clone)]
+pub struct BufferConsole {
+    pub input: VecDeque<u8>,
+    pub output: Vec<u8>,
+}
+
+impl BufferConsole {
+    pub fn new(input: &str) -> Self {
+        BufferConsole {
+            input: input.bytes().collect(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Uses the contents of the file as input.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        Ok(BufferConsole {
+            input: fs::read(path)?.into(),
+            output: Vec::new(),
+        })
+    }
+
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+}
+
+impl Console for BufferConsole {
+    fn read_char(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn write_char(&mut self, c: u8) {
+        self.output.push(c);
+    }
+}
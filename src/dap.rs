@@ -0,0 +1,565 @@
+//! Debug Adapter Protocol server that runs over stdin and stdout.
+//!
+//! Launch arguments are `program` (path to the `.asm` file), and optionally `stopOnEntry`, `os`
+//! to run traps through the bundled OS, and `input` or `inputFile` for console input.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::console::BufferConsole;
+use crate::debugger::{Debugger, Resume, StopReason, Watchpoint};
+use crate::simulator::MEMORY_SIZE;
+
+/// The only thread, since the LC3 has a single core.
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+const MEMORY_REFERENCE: u64 = 2;
+
+struct Session {
+    debugger: Debugger,
+    console: BufferConsole,
+    path: String,
+    stop_on_entry: bool,
+}
+
+struct Output {
+    seq: u64,
+}
+
+impl Output {
+    fn send(&mut self, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+
+        let body = message.to_string();
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = stdout.flush();
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn respond_error(&mut self, request: &Value, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }));
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+}
+
+/// Serves debug sessions until the client disconnects or closes stdin.
+pub fn run() {
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(io::stdin());
+        while let Some(message) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut server = Server {
+        requests,
+        pending: VecDeque::new(),
+        output: Output { seq: 0 },
+        session: None,
+    };
+    while let Some(request) = server.next_request() {
+        if !server.handle(&request) {
+            break;
+        }
+    }
+}
+
+/// Reads one `Content-Length` framed message, or `None` once stdin is closed.
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length?];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+struct Server {
+    requests: Receiver<Value>,
+    /// Requests received while the program was running, handled once it stops.
+    pending: VecDeque<Value>,
+    output: Output,
+    session: Option<Session>,
+}
+
+impl Server {
+    fn next_request(&mut self) -> Option<Value> {
+        self.pending
+            .pop_front()
+            .or_else(|| self.requests.recv().ok())
+    }
+
+    /// Handles a request, returning `false` once the session has ended.
+    fn handle(&mut self, request: &Value) -> bool {
+        let arguments = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or_default();
+
+        match command {
+            "initialize" => self.output.respond(
+                request,
+                json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsConditionalBreakpoints": true,
+                    "supportsDataBreakpoints": true,
+                    "supportsStepBack": true,
+                }),
+            ),
+            "launch" => match launch(arguments) {
+                Ok(session) => {
+                    self.session = Some(session);
+                    self.output.respond(request, json!({}));
+                    self.output.event("initialized", json!({}));
+                }
+                Err(message) => self.output.respond_error(request, &message),
+            },
+            "disconnect" | "terminate" => {
+                self.output.respond(request, json!({}));
+                return false;
+            }
+            "threads" => self.output.respond(
+                request,
+                json!({ "threads": [{ "id": THREAD_ID, "name": "LC-3" }] }),
+            ),
+            _ => {
+                let Some(session) = &mut self.session else {
+                    self.output
+                        .respond_error(request, "No program has been launched");
+                    return true;
+                };
+
+                match command {
+                    "setBreakpoints" => {
+                        let body = set_breakpoints(session, arguments);
+                        self.output.respond(request, body);
+                    }
+                    "dataBreakpointInfo" => {
+                        let body = data_breakpoint_info(session, arguments);
+                        self.output.respond(request, body);
+                    }
+                    "setDataBreakpoints" => {
+                        let body = set_data_breakpoints(session, arguments);
+                        self.output.respond(request, body);
+                    }
+                    "configurationDone" => {
+                        self.output.respond(request, json!({}));
+                        if session.stop_on_entry {
+                            session.debugger.mark_stopped();
+                            self.output.event(
+                                "stopped",
+                                json!({ "reason": "entry", "threadId": THREAD_ID }),
+                            );
+                        } else {
+                            self.resume(Resume::Continue);
+                        }
+                    }
+                    "stackTrace" => {
+                        let body = stack_trace(session);
+                        self.output.respond(request, body);
+                    }
+                    "scopes" => self.output.respond(
+                        request,
+                        json!({
+                            "scopes": [
+                                {
+                                    "name": "Registers",
+                                    "variablesReference": REGISTERS_REFERENCE,
+                                    "expensive": false,
+                                },
+                                {
+                                    "name": "Memory",
+                                    "variablesReference": MEMORY_REFERENCE,
+                                    "indexedVariables": MEMORY_SIZE,
+                                    "expensive": true,
+                                },
+                            ],
+                        }),
+                    ),
+                    "variables" => {
+                        let body = variables(session, arguments);
+                        self.output.respond(request, body);
+                    }
+                    "continue" => {
+                        self.output
+                            .respond(request, json!({ "allThreadsContinued": true }));
+                        self.resume(Resume::Continue);
+                    }
+                    "next" => {
+                        self.output.respond(request, json!({}));
+                        self.resume(Resume::Next);
+                    }
+                    "stepIn" => {
+                        self.output.respond(request, json!({}));
+                        self.resume(Resume::Step);
+                    }
+                    "stepOut" => {
+                        self.output.respond(request, json!({}));
+                        self.resume(Resume::StepOut);
+                    }
+                    "stepBack" => {
+                        self.output.respond(request, json!({}));
+                        self.resume(Resume::StepBack);
+                    }
+                    "reverseContinue" => {
+                        self.output.respond(request, json!({}));
+                        self.resume(Resume::ReverseContinue);
+                    }
+                    "pause" => {
+                        self.output.respond(request, json!({}));
+                        self.output.event(
+                            "stopped",
+                            json!({ "reason": "pause", "threadId": THREAD_ID }),
+                        );
+                    }
+                    _ => self
+                        .output
+                        .respond_error(request, &format!("Unsupported request `{}`", command)),
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Runs the program and reports why it stopped, along with anything it printed.
+    fn resume(&mut self, mode: Resume) {
+        let Some(session) = &mut self.session else {
+            return;
+        };
+
+        let requests = &self.requests;
+        let pending = &mut self.pending;
+        let output = &mut self.output;
+        let mut pause_requested = || {
+            let mut paused = false;
+            while let Ok(request) = requests.try_recv() {
+                if request["command"] == "pause" {
+                    output.respond(&request, json!({}));
+                    paused = true;
+                } else {
+                    pending.push_back(request);
+                }
+            }
+            paused
+        };
+        let reason = session
+            .debugger
+            .resume(mode, &mut session.console, &mut pause_requested);
+
+        if !session.console.output.is_empty() {
+            let text = session.console.output_string();
+            session.console.output.clear();
+            self.output
+                .event("output", json!({ "category": "stdout", "output": text }));
+        }
+
+        let reason = match reason {
+            StopReason::Step => "step",
+            StopReason::Breakpoint(_) | StopReason::Condition(_) => "breakpoint",
+            StopReason::Watchpoint(..) => "data breakpoint",
+            StopReason::Paused => "pause",
+            StopReason::StartOfHistory => {
+                self.output.event(
+                    "stopped",
+                    json!({
+                        "reason": "step",
+                        "description": "Reached the start of the recorded history",
+                        "threadId": THREAD_ID,
+                    }),
+                );
+                return;
+            }
+            StopReason::Halted => {
+                self.output.event("exited", json!({ "exitCode": 0 }));
+                self.output.event("terminated", json!({}));
+                return;
+            }
+            StopReason::Error(error) => {
+                self.output.event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("{}\n", error) }),
+                );
+                self.output.event(
+                    "stopped",
+                    json!({
+                        "reason": "exception",
+                        "description": error.to_string(),
+                        "text": error.to_string(),
+                        "threadId": THREAD_ID,
+                    }),
+                );
+                return;
+            }
+        };
+        self.output.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID }),
+        );
+    }
+}
+
+fn launch(arguments: &Value) -> Result<Session, String> {
+    let path = arguments["program"]
+        .as_str()
+        .ok_or("Missing `program` launch argument")?;
+    let source =
+        fs::read_to_string(path).map_err(|error| format!("Cannot read `{}`: {}", path, error))?;
+
+    let debugger = Debugger::load(&source, arguments["os"].as_bool().unwrap_or(false))
+        .map_err(|errors| format!("`{}` does not assemble:\n{}", path, errors.join("\n")))?;
+
+    let console = match (arguments["input"].as_str(), arguments["inputFile"].as_str()) {
+        (_, Some(input_path)) => BufferConsole::from_file(Path::new(input_path))
+            .map_err(|error| format!("Cannot read `{}`: {}", input_path, error))?,
+        (input, None) => BufferConsole::new(input.unwrap_or_default()),
+    };
+
+    Ok(Session {
+        debugger,
+        console,
+        path: path.to_owned(),
+        stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+    })
+}
+
+/// Replaces the breakpoints, moving each one to the next line with code. Breakpoints in other
+/// sources than the program are rejected.
+fn set_breakpoints(session: &mut Session, arguments: &Value) -> Value {
+    let requested = arguments["breakpoints"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    let source = arguments["source"]["path"].as_str().unwrap_or_default();
+    if !is_same_file(source, &session.path) {
+        let message = format!("Breakpoints can only be set in `{}`", session.path);
+        let breakpoints = (requested.iter())
+            .map(|breakpoint| {
+                json!({ "verified": false, "line": breakpoint["line"], "message": message })
+            })
+            .collect::<Vec<Value>>();
+        return json!({ "breakpoints": breakpoints });
+    }
+
+    let debugger = &mut session.debugger;
+    debugger.breakpoints.clear();
+
+    let breakpoints = requested
+        .iter()
+        .map(|breakpoint| {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+            let Some((address, line)) = debugger.address_at_line(line) else {
+                return json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No code at or after this line",
+                });
+            };
+
+            let condition = match breakpoint["condition"].as_str() {
+                Some(text) if !text.trim().is_empty() => match debugger.parse_condition(text) {
+                    Ok(condition) => Some(condition),
+                    Err(message) => {
+                        return json!({ "verified": false, "line": line, "message": message })
+                    }
+                },
+                _ => None,
+            };
+            debugger.breakpoints.insert(address, condition);
+            json!({ "verified": true, "line": line })
+        })
+        .collect::<Vec<Value>>();
+
+    json!({ "breakpoints": breakpoints })
+}
+
+/// Whether the paths name the same file, comparing them as given if either does not exist.
+fn is_same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Memory words can be watched by the name they are shown with, e.g. `x4000`.
+fn data_breakpoint_info(session: &Session, arguments: &Value) -> Value {
+    let name = arguments["name"].as_str().unwrap_or_default();
+    let address = match arguments["variablesReference"].as_u64() {
+        Some(MEMORY_REFERENCE) => session.debugger.parse_word(name),
+        _ => None,
+    };
+
+    match address {
+        Some(address) => json!({
+            "dataId": format!("x{:04X}", address),
+            "description": format!("Memory at x{:04X}", address),
+            "accessTypes": ["read", "write", "readWrite"],
+        }),
+        None => json!({
+            "dataId": null,
+            "description": "Only memory words can be watched",
+        }),
+    }
+}
+
+/// Replaces the watchpoints.
+fn set_data_breakpoints(session: &mut Session, arguments: &Value) -> Value {
+    let debugger = &mut session.debugger;
+    debugger.watchpoints.clear();
+
+    let breakpoints = arguments["breakpoints"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|breakpoint| {
+            let data_id = breakpoint["dataId"].as_str().unwrap_or_default();
+            let Some(address) = debugger.parse_word(data_id) else {
+                return json!({
+                    "verified": false,
+                    "message": format!("Unknown address `{}`", data_id),
+                });
+            };
+
+            let access_type = breakpoint["accessType"].as_str().unwrap_or("write");
+            debugger.watchpoints.push(Watchpoint {
+                start: address,
+                end: address,
+                read: access_type != "write",
+                write: access_type != "read",
+            });
+            json!({ "verified": true })
+        })
+        .collect::<Vec<Value>>();
+
+    json!({ "breakpoints": breakpoints })
+}
+
+/// The current instruction, followed by the call sites of the routines that have been entered.
+fn stack_trace(session: &Session) -> Value {
+    let debugger = &session.debugger;
+    let source = json!({
+        "name": Path::new(&session.path).file_name().map(|name| name.to_string_lossy()),
+        "path": session.path,
+    });
+
+    let addresses = std::iter::once(debugger.machine.pc).chain(
+        debugger
+            .call_stack
+            .iter()
+            .rev()
+            .map(|frame| frame.call_site),
+    );
+    let frames = addresses
+        .enumerate()
+        .map(|(id, address)| {
+            let name = match debugger.label_before(address) {
+                Some(label) => format!("{} (x{:04X})", label, address),
+                None => format!("x{:04X}", address),
+            };
+            json!({
+                "id": id,
+                "name": name,
+                "source": source,
+                "line": debugger.line_at(address).unwrap_or(0),
+                "column": 1,
+                "instructionPointerReference": format!("x{:04X}", address),
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    json!({ "stackFrames": frames, "totalFrames": frames.len() })
+}
+
+fn variables(session: &Session, arguments: &Value) -> Value {
+    let machine = &session.debugger.machine;
+
+    let variables = match arguments["variablesReference"].as_u64() {
+        Some(REGISTERS_REFERENCE) => {
+            let mut registers = machine
+                .registers
+                .iter()
+                .enumerate()
+                .map(|(i, &value)| word_variable(&format!("R{}", i), value))
+                .collect::<Vec<Value>>();
+            registers.push(word_variable("PC", machine.pc));
+            registers.push(word_variable("PSR", machine.psr));
+            registers.push(json!({
+                "name": "CC",
+                "value": machine.condition_codes(),
+                "variablesReference": 0,
+            }));
+            registers
+        }
+        Some(MEMORY_REFERENCE) => {
+            let start = arguments["start"].as_u64().unwrap_or(0) as usize;
+            let count = match arguments["count"].as_u64() {
+                Some(count) if count > 0 => count as usize,
+                _ => MEMORY_SIZE,
+            };
+            (start.min(MEMORY_SIZE)..(start + count).min(MEMORY_SIZE))
+                .map(|address| {
+                    let value = machine.read_memory(address as u16);
+                    word_variable(&format!("x{:04X}", address), value)
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+
+    json!({ "variables": variables })
+}
+
+/// Variable showing a word in hex and as a signed decimal.
+fn word_variable(name: &str, value: u16) -> Value {
+    json!({
+        "name": name,
+        "value": format!("x{:04X} (#{})", value, value as i16),
+        "variablesReference": 0,
+    })
+}
@@ -0,0 +1,490 @@
+//! Debugger core shared by the debug adapter and the terminal debugger.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::assembler::{self, Program};
+use crate::ast::Node;
+use crate::condition::{self, Condition};
+use crate::console::Console;
+use crate::simulator::{Machine, MemoryWrite, SimulatorError};
+
+/// Number of instructions executed between checks for a pause request.
+const PAUSE_CHECK_INTERVAL: u64 = 10_000;
+/// Default number of steps that can be undone.
+const HISTORY_LIMIT: usize = 100_000;
+
+pub struct Debugger {
+    pub machine: Machine,
+    pub program: Program,
+    pub nodes: Vec<Node>,
+    /// Addresses that stop execution when they are reached and their condition, if any, holds.
+    pub breakpoints: BTreeMap<u16, Option<Condition>>,
+    /// Conditions that stop execution wherever they become true.
+    pub conditions: Vec<Condition>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// Subroutines, trap routines and interrupt handlers that have been entered, outermost first.
+    pub call_stack: Vec<Frame>,
+    /// Address that execution last stopped at, so resuming from a breakpoint there does not stop
+    /// again before executing anything.
+    stopped_at: Option<u16>,
+    /// Changes made by the most recent steps, oldest first. Console input and output are not
+    /// recorded, so stepping back does not unread or unprint characters.
+    history: VecDeque<Delta>,
+    pub history_limit: usize,
+}
+
+/// Changes made by one step, holding the state from before it ran.
+#[derive(Debug, Clone)]
+struct Delta {
+    pc: u16,
+    psr: u16,
+    saved_ssp: u16,
+    saved_usp: u16,
+    halted: bool,
+    registers: Vec<(usize, u16)>,
+    reads: Vec<u16>,
+    writes: Vec<MemoryWrite>,
+    call_stack_len: usize,
+    /// Frame popped off the call stack by the step.
+    popped: Option<Frame>,
+}
+
+/// Routine entered by a call, a trap or an interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Address of the instruction that entered the routine, or that was interrupted.
+    pub call_site: u16,
+    /// Address execution continues at when the routine returns.
+    pub return_address: u16,
+    /// Entered by an interrupt or exception, so only `RTI` returns from it.
+    pub interrupt: bool,
+}
+
+/// Address range that stops execution when it is read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    /// Last address of the range.
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Watchpoint {
+    pub fn watches(&self, address: u16, access: Access) -> bool {
+        let watched = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+        watched && (self.start..=self.end).contains(&address)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Executes a single instruction.
+    Step,
+    /// Executes a single instruction, running `JSR`, `JSRR` and `TRAP` calls to completion.
+    Next,
+    /// Runs until the current subroutine or trap routine returns.
+    StepOut,
+    /// Runs until a breakpoint is reached.
+    Continue,
+    /// Undoes a single instruction.
+    StepBack,
+    /// Runs backward until a breakpoint is reached.
+    ReverseContinue,
+    /// Runs backward until just before the last write to the address.
+    ReverseToWrite(u16),
+}
+
+impl Resume {
+    pub fn is_reverse(&self) -> bool {
+        matches!(
+            self,
+            Resume::StepBack | Resume::ReverseContinue | Resume::ReverseToWrite(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Step,
+    Breakpoint(u16),
+    /// The condition with the given index became true.
+    Condition(usize),
+    /// A watched address was accessed.
+    Watchpoint(u16, Access),
+    Paused,
+    /// There is no more history to step back through.
+    StartOfHistory,
+    Halted,
+    Error(SimulatorError),
+}
+
+impl Debugger {
+    /// Assembles the source and loads it, along with the bundled OS if `os` is set. Returns the
+    /// errors formatted as `line:col: message` if the source does not assemble.
+    pub fn load(source: &str, os: bool) -> Result<Self, Vec<String>> {
+        let (program, nodes) = assembler::assemble_source(source)?;
+
+        let mut machine = Machine::new();
+        if os {
+            machine.load_os();
+        }
+        machine.load(&program);
+
+        Ok(Debugger {
+            machine,
+            program,
+            nodes,
+            breakpoints: BTreeMap::new(),
+            conditions: Vec::new(),
+            watchpoints: Vec::new(),
+            call_stack: Vec::new(),
+            stopped_at: None,
+            history: VecDeque::new(),
+            history_limit: HISTORY_LIMIT,
+        })
+    }
+
+    /// Node that the word at the address was assembled from.
+    pub fn node_at(&self, address: u16) -> Option<&Node> {
+        self.program
+            .source_map
+            .get(&address)
+            .map(|&index| &self.nodes[index])
+    }
+
+    /// Line, counting from 1, that the word at the address was assembled from.
+    pub fn line_at(&self, address: u16) -> Option<usize> {
+        self.node_at(address).map(|node| node.start_loc.line)
+    }
+
+    /// First address assembled from the line, or from the closest line after it if the line has
+    /// no code. Returns the address and the line it belongs to.
+    pub fn address_at_line(&self, line: usize) -> Option<(u16, usize)> {
+        self.program
+            .source_map
+            .iter()
+            .map(|(&address, &index)| (address, self.nodes[index].start_loc.line))
+            .filter(|&(_, address_line)| address_line >= line)
+            .min_by_key(|&(address, address_line)| (address_line, address))
+    }
+
+    /// Parses a label or a number literal such as `x3000`, `#10` or `10`.
+    pub fn parse_word(&self, text: &str) -> Option<u16> {
+        condition::parse_word(text, &self.program.symbols)
+    }
+
+    pub fn parse_condition(&self, text: &str) -> Result<Condition, String> {
+        Condition::parse(text, &self.program.symbols)
+    }
+
+    /// Closest label at or before the address, used to name stack frames.
+    pub fn label_before(&self, address: u16) -> Option<&str> {
+        self.program
+            .symbols
+            .iter()
+            .filter(|(_, &label_address)| label_address <= address)
+            .max_by_key(|(_, &label_address)| label_address)
+            .map(|(label, _)| label.as_str())
+    }
+
+    /// Forgets the recorded history, e.g. after the state has been edited by hand.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Executes instructions, or undoes them for the reverse modes, until the resume mode is
+    /// satisfied, a breakpoint is reached, the machine halts or fails. `pause_requested` is
+    /// checked periodically so long runs can be interrupted.
+    pub fn resume(
+        &mut self,
+        mode: Resume,
+        console: &mut dyn Console,
+        pause_requested: &mut dyn FnMut() -> bool,
+    ) -> StopReason {
+        // A breakpoint on the first instruction is only reached by starting there
+        let reason = if mode == Resume::Continue
+            && self.stopped_at != Some(self.machine.pc)
+            && self.at_breakpoint()
+        {
+            StopReason::Breakpoint(self.machine.pc)
+        } else {
+            self.run_until_stop(mode, console, pause_requested)
+        };
+
+        self.mark_stopped();
+        reason
+    }
+
+    /// Records that execution is stopped at the PC without having resumed, e.g. when stopping
+    /// on entry.
+    pub fn mark_stopped(&mut self) {
+        self.stopped_at = Some(self.machine.pc);
+    }
+
+    fn run_until_stop(
+        &mut self,
+        mode: Resume,
+        console: &mut dyn Console,
+        pause_requested: &mut dyn FnMut() -> bool,
+    ) -> StopReason {
+        let depth = self.call_stack.len();
+        let mut steps = 0;
+        let mut conditions_held = (self.conditions.iter())
+            .map(|condition| condition.holds(&self.machine))
+            .collect::<Vec<bool>>();
+
+        loop {
+            steps += 1;
+            if steps % PAUSE_CHECK_INTERVAL == 0 && pause_requested() {
+                return StopReason::Paused;
+            }
+
+            if mode.is_reverse() {
+                let Some(delta) = self.step_back() else {
+                    return StopReason::StartOfHistory;
+                };
+
+                match mode {
+                    Resume::ReverseToWrite(address) => {
+                        if delta.writes.iter().any(|write| write.address == address) {
+                            return StopReason::Watchpoint(address, Access::Write);
+                        }
+                        continue;
+                    }
+                    _ => {
+                        let watched = self.watched_access(&delta.reads, &delta.writes);
+                        if let Some((address, access)) = watched {
+                            return StopReason::Watchpoint(address, access);
+                        }
+                        if mode == Resume::StepBack {
+                            return StopReason::Step;
+                        }
+                    }
+                }
+            } else {
+                if self.machine.halted {
+                    return StopReason::Halted;
+                }
+                if let Err(error) = self.step(console) {
+                    return StopReason::Error(error);
+                }
+                if self.machine.halted {
+                    return StopReason::Halted;
+                }
+                let watched = self.watched_access(&self.machine.reads, &self.machine.writes);
+                if let Some((address, access)) = watched {
+                    return StopReason::Watchpoint(address, access);
+                }
+
+                match mode {
+                    Resume::Step => return StopReason::Step,
+                    Resume::Next if self.call_stack.len() <= depth => return StopReason::Step,
+                    Resume::StepOut if self.call_stack.len() < depth => return StopReason::Step,
+                    _ => {}
+                }
+            }
+
+            if self.at_breakpoint() {
+                return StopReason::Breakpoint(self.machine.pc);
+            }
+            for (index, condition) in self.conditions.iter().enumerate() {
+                let held =
+                    std::mem::replace(&mut conditions_held[index], condition.holds(&self.machine));
+                if conditions_held[index] && !held {
+                    return StopReason::Condition(index);
+                }
+            }
+        }
+    }
+
+    /// Whether the PC is on a breakpoint whose condition, if any, holds.
+    fn at_breakpoint(&self) -> bool {
+        (self.breakpoints.get(&self.machine.pc))
+            .is_some_and(|condition| condition.as_ref().is_none_or(|c| c.holds(&self.machine)))
+    }
+
+    /// First of the accesses made by a step that a watchpoint covers.
+    fn watched_access(&self, reads: &[u16], writes: &[MemoryWrite]) -> Option<(u16, Access)> {
+        let reads = reads.iter().map(|&address| (address, Access::Read));
+        let writes = writes.iter().map(|write| (write.address, Access::Write));
+        reads.chain(writes).find(|&(address, access)| {
+            (self.watchpoints.iter()).any(|watchpoint| watchpoint.watches(address, access))
+        })
+    }
+
+    /// Executes a single instruction, keeping track of the calls that have been entered and
+    /// recording how to undo it.
+    fn step(&mut self, console: &mut dyn Console) -> Result<(), SimulatorError> {
+        let registers = self.machine.registers;
+        let mut delta = Delta {
+            pc: self.machine.pc,
+            psr: self.machine.psr,
+            saved_ssp: self.machine.saved_ssp,
+            saved_usp: self.machine.saved_usp,
+            halted: self.machine.halted,
+            registers: Vec::new(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            call_stack_len: self.call_stack.len(),
+            popped: self.call_stack.last().copied(),
+        };
+
+        let address = self.machine.pc;
+        let instruction = self.machine.read_memory(address);
+        self.machine.step(console)?;
+
+        let return_address = address.wrapping_add(1);
+        let returned =
+            (self.call_stack.last()).is_some_and(|frame| frame.return_address == self.machine.pc);
+        match instruction >> 12 {
+            // The instruction was interrupted, or faulted and the handler returns past it
+            _ if self.machine.interrupt.is_some() => {
+                let supervisor_stack = self.machine.registers[6];
+                self.call_stack.push(Frame {
+                    call_site: address,
+                    return_address: self.machine.read_memory(supervisor_stack),
+                    interrupt: true,
+                });
+            }
+            // JSR, JSRR, TRAP
+            0b0100 | 0b1111 if self.machine.pc != return_address => {
+                self.call_stack.push(Frame {
+                    call_site: address,
+                    return_address,
+                    interrupt: false,
+                });
+            }
+            // RTI
+            0b1000 if returned => {
+                self.call_stack.pop();
+            }
+            // JMP, RET
+            0b1100 if returned && !self.call_stack.last().is_some_and(|frame| frame.interrupt) => {
+                self.call_stack.pop();
+            }
+            _ => {}
+        }
+
+        if self.history_limit > 0 {
+            if self.history.len() >= self.history_limit {
+                self.history.pop_front();
+            }
+
+            delta.registers = (0..8)
+                .filter(|&r| registers[r] != self.machine.registers[r])
+                .map(|r| (r, registers[r]))
+                .collect();
+            delta.reads = self.machine.reads.clone();
+            delta.writes = self.machine.writes.clone();
+            if self.call_stack.len() >= delta.call_stack_len {
+                delta.popped = None;
+            }
+            self.history.push_back(delta);
+        }
+
+        Ok(())
+    }
+
+    /// Undoes the most recent step, returning what it changed.
+    fn step_back(&mut self) -> Option<Delta> {
+        let delta = self.history.pop_back()?;
+
+        for write in delta.writes.iter().rev() {
+            self.machine.write_memory(write.address, write.before);
+        }
+        for &(register, value) in &delta.registers {
+            self.machine.registers[register] = value;
+        }
+        self.machine.pc = delta.pc;
+        self.machine.psr = delta.psr;
+        self.machine.saved_ssp = delta.saved_ssp;
+        self.machine.saved_usp = delta.saved_usp;
+        self.machine.halted = delta.halted;
+
+        if self.call_stack.len() > delta.call_stack_len {
+            self.call_stack.pop();
+        } else if let Some(frame) = delta.popped {
+            self.call_stack.push(frame);
+        }
+
+        Some(delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+    use crate::simulator::{INTERRUPT_VECTOR_TABLE, KBSR, KEYBOARD_VECTOR};
+
+    #[test]
+    fn keyboard_interrupts_push_and_pop_their_own_frame() {
+        let mut debugger =
+            Debugger::load(".ORIG x3000\nJSR SUB\nHALT\nSUB RET\n.END", false).unwrap();
+        // Handler that reads the character through KBDR and returns
+        let handler = [0xA001, 0x8000, 0xFE02];
+        debugger.machine.memory[0x5000..0x5003].copy_from_slice(&handler);
+        debugger.machine.memory[INTERRUPT_VECTOR_TABLE as usize + KEYBOARD_VECTOR as usize] =
+            0x5000;
+        debugger.machine.memory[KBSR as usize] = 0x4000;
+        let mut console = BufferConsole::new("k");
+
+        // The interrupt is taken before the `JSR` runs, which must not be recorded as a call
+        debugger.step(&mut console).unwrap();
+        assert_eq!(debugger.machine.pc, 0x5000);
+        assert_eq!(
+            debugger.call_stack,
+            [Frame {
+                call_site: 0x3000,
+                return_address: 0x3000,
+                interrupt: true,
+            }]
+        );
+
+        debugger.step(&mut console).unwrap();
+        debugger.step(&mut console).unwrap();
+        assert_eq!(debugger.machine.pc, 0x3000);
+        assert_eq!(debugger.call_stack, []);
+
+        debugger.step(&mut console).unwrap();
+        assert_eq!(debugger.call_stack.len(), 1);
+        assert!(!debugger.call_stack[0].interrupt);
+        debugger.step(&mut console).unwrap();
+        assert_eq!(debugger.call_stack, []);
+
+        // Undoing the steps restores the frames
+        for _ in 0..4 {
+            debugger.step_back().unwrap();
+        }
+        assert_eq!(debugger.machine.pc, 0x5000);
+        assert!(debugger.call_stack[0].interrupt);
+    }
+
+    #[test]
+    fn next_runs_interrupt_handlers_to_completion() {
+        let mut debugger =
+            Debugger::load(".ORIG x3000\nADD R0, R0, #1\nHALT\n.END", false).unwrap();
+        let handler = [0xA001, 0x8000, 0xFE02];
+        debugger.machine.memory[0x5000..0x5003].copy_from_slice(&handler);
+        debugger.machine.memory[INTERRUPT_VECTOR_TABLE as usize + KEYBOARD_VECTOR as usize] =
+            0x5000;
+        debugger.machine.memory[KBSR as usize] = 0x4000;
+        let mut console = BufferConsole::new("k");
+
+        let reason = debugger.resume(Resume::Next, &mut console, &mut || false);
+        assert_eq!(reason, StopReason::Step);
+        assert_eq!(debugger.machine.pc, 0x3000);
+        assert_eq!(debugger.machine.registers[0], u16::from(b'k'));
+        assert_eq!(debugger.call_stack, []);
+    }
+}
@@ -0,0 +1,58 @@
+//! Disassembler that turns machine words back into instructions.
+
+use crate::simulator::sign_extend;
+
+/// Disassembles the word stored at the address. PC-relative operands are shown as the address
+/// they refer to, and words that are not instructions as `.FILL`.
+pub fn disassemble(word: u16, address: u16) -> String {
+    let dr = (word >> 9) & 0x7;
+    let sr1 = (word >> 6) & 0x7;
+    let next = address.wrapping_add(1);
+    let pc_offset9 = next.wrapping_add(sign_extend(word, 9));
+
+    match word >> 12 {
+        0b0000 if dr == 0 => "NOP".to_owned(),
+        0b0000 => {
+            let flags = [(0x4, 'n'), (0x2, 'z'), (0x1, 'p')]
+                .iter()
+                .filter(|(flag, _)| dr & flag != 0)
+                .map(|(_, c)| *c)
+                .collect::<String>();
+            format!("BR{} x{:04X}", flags, pc_offset9)
+        }
+        0b0001 | 0b0101 => {
+            let name = if word >> 12 == 0b0001 { "ADD" } else { "AND" };
+            if word & 0x20 != 0 {
+                let imm5 = sign_extend(word, 5) as i16;
+                format!("{} R{}, R{}, #{}", name, dr, sr1, imm5)
+            } else {
+                format!("{} R{}, R{}, R{}", name, dr, sr1, word & 0x7)
+            }
+        }
+        0b0010 => format!("LD R{}, x{:04X}", dr, pc_offset9),
+        0b1010 => format!("LDI R{}, x{:04X}", dr, pc_offset9),
+        0b0011 => format!("ST R{}, x{:04X}", dr, pc_offset9),
+        0b1011 => format!("STI R{}, x{:04X}", dr, pc_offset9),
+        0b1110 => format!("LEA R{}, x{:04X}", dr, pc_offset9),
+        0b0110 => format!("LDR R{}, R{}, #{}", dr, sr1, sign_extend(word, 6) as i16),
+        0b0111 => format!("STR R{}, R{}, #{}", dr, sr1, sign_extend(word, 6) as i16),
+        0b1001 => format!("NOT R{}, R{}", dr, sr1),
+        0b1100 if sr1 == 7 => "RET".to_owned(),
+        0b1100 => format!("JMP R{}", sr1),
+        0b0100 if word & 0x800 != 0 => {
+            format!("JSR x{:04X}", next.wrapping_add(sign_extend(word, 11)))
+        }
+        0b0100 => format!("JSRR R{}", sr1),
+        0b1000 => "RTI".to_owned(),
+        0b1111 => match word & 0xFF {
+            0x20 => "GETC".to_owned(),
+            0x21 => "OUT".to_owned(),
+            0x22 => "PUTS".to_owned(),
+            0x23 => "IN".to_owned(),
+            0x24 => "PUTSP".to_owned(),
+            0x25 => "HALT".to_owned(),
+            vector => format!("TRAP x{:02X}", vector),
+        },
+        _ => format!(".FILL x{:04X}", word),
+    }
+}
@@ -0,0 +1,437 @@
+//! Autograder that runs a program against the test cases in a TOML or YAML spec. Specs ending
+//! in `.yaml` or `.yml` are read as YAML, with the same keys as TOML:
+//!
+//! ```toml
+//! program = "lab2.asm"   # relative to the spec
+//! os = false             # run traps through the bundled OS
+//! max_steps = 100000     # default instruction limit for every test
+//!
+//! [[test]]
+//! name = "adds two numbers"
+//! input = "12+3*="
+//! registers = { R5 = "x4000" }
+//! memory = { BUFFER = [1, 2, "x0003"] }
+//!
+//! [test.expect]
+//! output = "0009"
+//! registers = { R0 = "x0039", CC = "p" }
+//! memory = { "x4000" = "#9" }
+//! ```
+//!
+//! Words are integers or strings holding a number literal or a label. Memory keys are addresses or
+//! labels, and a list of words covers consecutive addresses. Tests are expected to halt unless
+//! `expect.halt` is `false`. A test with invalid values, e.g. an unknown label, fails instead of
+//! stopping the other tests from running.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::assembler::{self, Program};
+use crate::condition::parse_word;
+use crate::console::BufferConsole;
+use crate::simulator::Machine;
+
+const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    pub program: String,
+    #[serde(default)]
+    pub os: bool,
+    pub max_steps: Option<u64>,
+    #[serde(rename = "test", default)]
+    pub tests: Vec<TestCase>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestCase {
+    pub name: String,
+    #[serde(default)]
+    pub input: String,
+    pub max_steps: Option<u64>,
+    #[serde(default)]
+    pub registers: BTreeMap<String, Word>,
+    #[serde(default)]
+    pub memory: BTreeMap<String, Words>,
+    #[serde(default)]
+    pub expect: Expectations,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectations {
+    pub output: Option<String>,
+    #[serde(default)]
+    pub registers: BTreeMap<String, Word>,
+    #[serde(default)]
+    pub memory: BTreeMap<String, Words>,
+    pub halt: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Word {
+    Number(i64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Words {
+    One(Word),
+    Many(Vec<Word>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub program: String,
+    pub passed: usize,
+    pub failed: usize,
+    pub tests: Vec<TestResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestResult {
+    pub name: String,
+    pub passed: bool,
+    pub steps: u64,
+    pub output: String,
+    pub failures: Vec<Failure>,
+}
+
+/// Something that differed from the spec, e.g. a register or the output.
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub subject: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Reads the spec, assembles its program and runs every test. Returns an error if the spec or
+/// the program is invalid.
+pub fn run(spec_path: &Path) -> Result<Report, String> {
+    let spec_text = fs::read_to_string(spec_path)
+        .map_err(|error| format!("Cannot read `{}`: {}", spec_path.display(), error))?;
+    let extension = spec_path
+        .extension()
+        .and_then(|extension| extension.to_str());
+    let spec = match extension {
+        Some("yaml" | "yml") => serde_yaml::from_str::<Spec>(&spec_text).map_err(|e| e.to_string()),
+        _ => toml::from_str::<Spec>(&spec_text).map_err(|e| e.to_string()),
+    }
+    .map_err(|error| format!("Invalid spec `{}`: {}", spec_path.display(), error))?;
+
+    let program_path = spec_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(&spec.program);
+    let source = fs::read_to_string(&program_path)
+        .map_err(|error| format!("Cannot read `{}`: {}", program_path.display(), error))?;
+    let (program, _) = assembler::assemble_source(&source).map_err(|errors| {
+        format!(
+            "`{}` does not assemble:\n{}",
+            program_path.display(),
+            errors.join("\n")
+        )
+    })?;
+
+    let mut machine = Machine::new();
+    if spec.os {
+        machine.load_os();
+    }
+    machine.load(&program);
+
+    let tests = spec
+        .tests
+        .iter()
+        .map(|test| {
+            let max_steps = test.max_steps.or(spec.max_steps);
+            run_test(test, &program, machine.clone(), max_steps).unwrap_or_else(|error| {
+                TestResult {
+                    name: test.name.clone(),
+                    passed: false,
+                    steps: 0,
+                    output: String::new(),
+                    failures: vec![Failure {
+                        subject: "test".to_owned(),
+                        expected: "a valid test case".to_owned(),
+                        actual: error,
+                    }],
+                }
+            })
+        })
+        .collect::<Vec<TestResult>>();
+
+    let passed = tests.iter().filter(|test| test.passed).count();
+    Ok(Report {
+        program: program_path.display().to_string(),
+        passed,
+        failed: tests.len() - passed,
+        tests,
+    })
+}
+
+fn run_test(
+    test: &TestCase,
+    program: &Program,
+    mut machine: Machine,
+    max_steps: Option<u64>,
+) -> Result<TestResult, String> {
+    for (register, value) in &test.registers {
+        let value = resolve(value, program)?;
+        match register.to_uppercase().as_str() {
+            "PC" => machine.pc = value,
+            "PSR" => machine.psr = value,
+            name => machine.registers[register_index(name)?] = value,
+        }
+    }
+    for (address, words) in &test.memory {
+        let address = resolve_address(address, program)?;
+        for (offset, word) in words.iter().enumerate() {
+            let value = resolve(word, program)?;
+            machine.write_memory(address.wrapping_add(offset as u16), value);
+        }
+    }
+
+    let max_steps = max_steps.unwrap_or(DEFAULT_MAX_STEPS);
+    let mut console = BufferConsole::new(&test.input);
+    let mut failures = Vec::new();
+
+    let mut steps = 0;
+    while !machine.halted && steps < max_steps {
+        if let Err(error) = machine.step(&mut console) {
+            failures.push(Failure {
+                subject: "execution".to_owned(),
+                expected: "no errors".to_owned(),
+                actual: error.to_string(),
+            });
+            break;
+        }
+        steps += 1;
+    }
+
+    let expect = &test.expect;
+    if failures.is_empty() && expect.halt.unwrap_or(true) && !machine.halted {
+        failures.push(Failure {
+            subject: "halt".to_owned(),
+            expected: format!("HALT within {} steps", max_steps),
+            actual: format!("still running at x{:04X}", machine.pc),
+        });
+    }
+
+    let output = console.output_string();
+    if let Some(expected) = &expect.output {
+        if *expected != output {
+            failures.push(Failure {
+                subject: "output".to_owned(),
+                expected: format!("{:?}", expected),
+                actual: format!("{:?}", output),
+            });
+        }
+    }
+
+    for (register, expected) in &expect.registers {
+        let (expected, actual) = match register.to_uppercase().as_str() {
+            "CC" => match expected {
+                Word::Text(flags) => (flags.to_lowercase(), machine.condition_codes()),
+                Word::Number(_) => return Err("`CC` must be a string such as \"z\"".to_owned()),
+            },
+            "PC" => (hex(resolve(expected, program)?), hex(machine.pc)),
+            "PSR" => (hex(resolve(expected, program)?), hex(machine.psr)),
+            name => (
+                hex(resolve(expected, program)?),
+                hex(machine.registers[register_index(name)?]),
+            ),
+        };
+
+        if expected != actual {
+            failures.push(Failure {
+                subject: register.to_uppercase(),
+                expected,
+                actual,
+            });
+        }
+    }
+
+    for (address, words) in &expect.memory {
+        let address = resolve_address(address, program)?;
+        for (offset, word) in words.iter().enumerate() {
+            let address = address.wrapping_add(offset as u16);
+            let expected = hex(resolve(word, program)?);
+            let actual = hex(machine.read_memory(address));
+            if expected != actual {
+                failures.push(Failure {
+                    subject: format!("x{:04X}", address),
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+
+    Ok(TestResult {
+        name: test.name.clone(),
+        passed: failures.is_empty(),
+        steps,
+        output,
+        failures,
+    })
+}
+
+impl Words {
+    fn iter(&self) -> std::slice::Iter<'_, Word> {
+        match self {
+            Words::One(word) => std::slice::from_ref(word).iter(),
+            Words::Many(words) => words.iter(),
+        }
+    }
+}
+
+fn register_index(name: &str) -> Result<usize, String> {
+    match name.as_bytes() {
+        [b'R', digit @ b'0'..=b'7'] => Ok((digit - b'0') as usize),
+        _ => Err(format!("Unknown register `{}`", name)),
+    }
+}
+
+fn resolve(word: &Word, program: &Program) -> Result<u16, String> {
+    match word {
+        Word::Number(value) if (-0x8000..=0xFFFF).contains(value) => Ok(*value as u16),
+        Word::Number(value) => Err(format!("`{}` does not fit in 16 bits", value)),
+        Word::Text(text) => parse_word(text, &program.symbols)
+            .ok_or(format!("Unknown label or invalid number `{}`", text)),
+    }
+}
+
+fn resolve_address(text: &str, program: &Program) -> Result<u16, String> {
+    parse_word(text, &program.symbols).ok_or(format!("Unknown label or address `{}`", text))
+}
+
+fn hex(value: u16) -> String {
+    format!("x{:04X}", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const PROGRAM: &str = "
+        .ORIG x3000
+        GETC
+        OUT
+        ADD R1, R0, #1
+        ST R1, RESULT
+        HALT
+        RESULT .BLKW #1
+        .END
+    ";
+
+    /// Writes the program and the spec to a fresh directory, returning the spec's path.
+    fn write_spec(name: &str, spec: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("lc3-grader-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("program.asm"), PROGRAM).unwrap();
+        let spec_path = directory.join(name);
+        fs::write(&spec_path, spec).unwrap();
+        spec_path
+    }
+
+    fn failure_subjects(test: &TestResult) -> Vec<&str> {
+        test.failures
+            .iter()
+            .map(|failure| failure.subject.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn grades_toml_specs() {
+        let spec = r##"
+            program = "program.asm"
+
+            [[test]]
+            name = "echoes"
+            input = "a"
+
+            [test.expect]
+            output = "a"
+            registers = { R1 = "x0062", CC = "p" }
+            memory = { RESULT = 98 }
+
+            [[test]]
+            name = "wrong output"
+            input = "a"
+            expect = { output = "b", registers = { R1 = "#98" } }
+        "##;
+        let report = run(&write_spec("spec.toml", spec)).unwrap();
+
+        assert_eq!((report.passed, report.failed), (1, 1));
+        assert!(report.tests[0].passed);
+        assert_eq!(report.tests[0].output, "a");
+        assert_eq!(failure_subjects(&report.tests[1]), ["output"]);
+        assert_eq!(report.tests[1].failures[0].actual, "\"a\"");
+    }
+
+    #[test]
+    fn grades_yaml_specs() {
+        let spec = r#"
+program: program.asm
+max_steps: 100
+test:
+  - name: echoes
+    input: b
+    expect:
+      output: b
+      memory: { RESULT: x0063 }
+  - name: runs out of input
+    expect:
+      output: b
+"#;
+        let report = run(&write_spec("spec.yaml", spec)).unwrap();
+
+        assert_eq!((report.passed, report.failed), (1, 1));
+        assert!(report.tests[0].passed);
+        assert_eq!(failure_subjects(&report.tests[1]), ["execution", "output"]);
+    }
+
+    #[test]
+    fn invalid_tests_fail_without_stopping_the_others() {
+        let spec = r#"
+            program = "program.asm"
+
+            [[test]]
+            name = "bad register"
+            input = "a"
+            registers = { R9 = 1 }
+
+            [[test]]
+            name = "echoes"
+            input = "a"
+            expect = { output = "a" }
+        "#;
+        let report = run(&write_spec("invalid.toml", spec)).unwrap();
+
+        assert_eq!((report.passed, report.failed), (1, 1));
+        assert_eq!(failure_subjects(&report.tests[0]), ["test"]);
+        assert!(report.tests[1].passed);
+    }
+
+    #[test]
+    fn reports_invalid_specs() {
+        let error = run(&write_spec(
+            "unknown.toml",
+            "program = \"program.asm\"\nbogus = 1",
+        ))
+        .unwrap_err();
+        assert!(error.starts_with("Invalid spec"));
+
+        let error = run(&write_spec("missing.toml", "program = \"missing.asm\"")).unwrap_err();
+        assert!(error.starts_with("Cannot read"));
+    }
+}
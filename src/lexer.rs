@@ -5,7 +5,7 @@ use std::vec::IntoIter;
 
 use crate::tokens::{
     DirectiveTokenValue, FileLoc, NumberLiteralFormat, NumberLiteralTokenValue, OpcodeTokenValue,
-    RegisterTokenValue, Token, TokenValue, TrapRoutineTokenValue,
+    RegisterTokenValue, Token, TokenValue,
 };
 
 pub fn analyze(text: &str) -> Vec<Token> {
@@ -20,7 +20,7 @@ pub fn analyze(text: &str) -> Vec<Token> {
 
         for c in raw_data.by_ref() {
             match c {
-                c if c == '\n' => {
+                '\n' => {
                     first_char = Some(c);
                     break;
                 }
@@ -38,16 +38,16 @@ pub fn analyze(text: &str) -> Vec<Token> {
             None => {
                 break;
             }
-            Some(c) if c == '\n' => {
+            Some('\n') => {
                 tokens.push(Token {
                     value: TokenValue::NewLine,
-                    start_loc: start_loc,
-                    end_loc: end_loc,
+                    start_loc,
+                    end_loc,
                 });
 
                 end_loc.next_line();
             }
-            Some(c) if c == ',' => {
+            Some(',') => {
                 tokens.push(Token {
                     value: TokenValue::Comma,
                     start_loc,
@@ -56,21 +56,84 @@ pub fn analyze(text: &str) -> Vec<Token> {
 
                 end_loc.col += 1;
             }
-            Some(c) if c == '"' => {
+            Some('"') => {
                 let mut s = String::new();
-                get_next_char_while(&mut raw_data, &mut end_loc, &mut s, |c| c != '"');
-                raw_data.next();
+                let mut error = None;
+
+                loop {
+                    match raw_data.peek() {
+                        None | Some('\n') => {
+                            error = Some("Unterminated string literal".to_string());
+                            break;
+                        }
+                        Some('"') => {
+                            raw_data.next();
+                            end_loc.col += 1;
+                            break;
+                        }
+                        _ => match get_next_literal_char(&mut raw_data, &mut end_loc) {
+                            Ok(c) => s.push(c),
+                            Err(msg) => {
+                                error.get_or_insert(msg);
+                            }
+                        },
+                    }
+                }
+
+                tokens.push(Token {
+                    value: match error {
+                        Some(msg) => TokenValue::Error(msg),
+                        None => TokenValue::StringLiteral(s),
+                    },
+                    start_loc,
+                    end_loc,
+                });
 
                 end_loc.col += 1;
+            }
+            Some('\'') => {
+                let value = match raw_data.peek() {
+                    None | Some('\n' | '\'') => Err("Empty character literal".to_string()),
+                    _ => get_next_literal_char(&mut raw_data, &mut end_loc),
+                };
+
+                let value = match raw_data.peek() {
+                    Some('\'') => {
+                        raw_data.next();
+                        end_loc.col += 1;
+                        value
+                    }
+                    _ => {
+                        let mut rest = String::new();
+                        get_next_char_while(&mut raw_data, &mut end_loc, &mut rest, |c| {
+                            !c.is_whitespace() && c != '\''
+                        });
+
+                        if raw_data.peek() == Some(&'\'') {
+                            raw_data.next();
+                            end_loc.col += 1;
+                            Err("Character literal must contain exactly one character".to_string())
+                        } else {
+                            Err("Unterminated character literal".to_string())
+                        }
+                    }
+                };
+
                 tokens.push(Token {
-                    value: TokenValue::StringLiteral(s),
+                    value: match value {
+                        Ok(c) => TokenValue::NumberLiteral(NumberLiteralTokenValue {
+                            format: NumberLiteralFormat::Character,
+                            value: c.to_string(),
+                        }),
+                        Err(msg) => TokenValue::Error(msg),
+                    },
                     start_loc,
                     end_loc,
                 });
 
                 end_loc.col += 1;
             }
-            Some(c) if c == ';' => {
+            Some(';') => {
                 let mut comment = String::new();
                 get_next_char_while(&mut raw_data, &mut end_loc, &mut comment, |c| c != '\n');
 
@@ -82,26 +145,73 @@ pub fn analyze(text: &str) -> Vec<Token> {
 
                 end_loc.col += 1;
             }
-            Some(c) if c == '#' || c == 'x' => {
-                let mut value = String::new();
-                get_next_char_while(&mut raw_data, &mut end_loc, &mut value, |c| {
-                    !c.is_whitespace() && c != ','
+            Some('+') => {
+                tokens.push(Token {
+                    value: TokenValue::Plus,
+                    start_loc,
+                    end_loc,
+                });
+
+                end_loc.col += 1;
+            }
+            Some('-') => {
+                tokens.push(Token {
+                    value: TokenValue::Minus,
+                    start_loc,
+                    end_loc,
                 });
 
+                end_loc.col += 1;
+            }
+            Some(c) if c == '#' || c == 'x' || c == 'X' || c.is_ascii_digit() => {
+                let mut text = c.to_string();
                 let format = match c {
                     '#' => NumberLiteralFormat::Decimal,
-                    _ => NumberLiteralFormat::Hex,
+                    'x' | 'X' => NumberLiteralFormat::Hex,
+                    '0' if matches!(raw_data.peek(), Some('x' | 'X')) => {
+                        // Alternative `0x` hex prefix
+                        text.extend(raw_data.next());
+                        end_loc.col += 1;
+                        NumberLiteralFormat::Hex
+                    }
+                    _ => NumberLiteralFormat::Decimal,
+                };
+                let prefix_len = if c.is_ascii_digit() && text.len() == 1 {
+                    0
+                } else {
+                    text.len()
+                };
+
+                if prefix_len > 0 && raw_data.peek() == Some(&'-') {
+                    // Negative literal such as `#-1`, as opposed to a subtraction
+                    get_next_char_while(&mut raw_data, &mut end_loc, &mut text, |c| c == '-');
+                }
+                get_next_char_while(&mut raw_data, &mut end_loc, &mut text, |c| {
+                    !c.is_whitespace() && !",+-".contains(c)
+                });
+
+                let literal = NumberLiteralTokenValue {
+                    format,
+                    value: text[prefix_len..].to_string(),
+                };
+
+                let value = match number_literal_token_value(literal, &text) {
+                    // Like `b`, an `x` without hex digits after it starts a label such as `xcount`
+                    TokenValue::Error(_) if c.eq_ignore_ascii_case(&'x') && !text.contains('-') => {
+                        TokenValue::Label(text)
+                    }
+                    value => value,
                 };
 
                 tokens.push(Token {
-                    value: TokenValue::NumberLiteral(NumberLiteralTokenValue { format, value }),
+                    value,
                     start_loc,
                     end_loc,
                 });
 
                 end_loc.col += 1;
             }
-            Some(c) if c == '.' => {
+            Some('.') => {
                 let mut directive = String::new();
                 get_next_char_while(&mut raw_data, &mut end_loc, &mut directive, |c| {
                     !c.is_whitespace()
@@ -127,13 +237,31 @@ pub fn analyze(text: &str) -> Vec<Token> {
             Some(c) => 'case: {
                 let mut label = c.to_string();
                 get_next_char_while(&mut raw_data, &mut end_loc, &mut label, |c| {
-                    !c.is_whitespace() && c != ','
+                    !c.is_whitespace() && !",+-".contains(c)
                 });
                 let label_upper = label.to_ascii_uppercase();
 
+                // Binary literals such as `b0101`, anything else starting with `b` is a label
+                if let Some(digits) = label_upper.strip_prefix('B') {
+                    if !digits.is_empty() && digits.chars().all(|c| c == '0' || c == '1') {
+                        let literal = NumberLiteralTokenValue {
+                            format: NumberLiteralFormat::Binary,
+                            value: digits.to_string(),
+                        };
+
+                        tokens.push(Token {
+                            value: number_literal_token_value(literal, &label),
+                            start_loc,
+                            end_loc,
+                        });
+
+                        end_loc.col += 1;
+                        break 'case;
+                    }
+                }
+
                 // Special case with BR opcode with optional NZP suffix
-                if label_upper.starts_with("BR") {
-                    let suffix = &label_upper[2..];
+                if let Some(suffix) = label_upper.strip_prefix("BR") {
                     if suffix.chars().all(|c| "NZP".contains(c)) {
                         tokens.push(Token {
                             value: TokenValue::Opcode(OpcodeTokenValue::BR {
@@ -156,23 +284,25 @@ pub fn analyze(text: &str) -> Vec<Token> {
                     "AND" => TokenValue::Opcode(OpcodeTokenValue::AND),
                     "JMP" => TokenValue::Opcode(OpcodeTokenValue::JMP),
                     "JSR" => TokenValue::Opcode(OpcodeTokenValue::JSR),
+                    "JSRR" => TokenValue::Opcode(OpcodeTokenValue::JSRR),
                     "LD" => TokenValue::Opcode(OpcodeTokenValue::LD),
                     "LDI" => TokenValue::Opcode(OpcodeTokenValue::LDI),
                     "LDR" => TokenValue::Opcode(OpcodeTokenValue::LDR),
                     "LEA" => TokenValue::Opcode(OpcodeTokenValue::LEA),
                     "NOT" => TokenValue::Opcode(OpcodeTokenValue::NOT),
                     "RET" => TokenValue::Opcode(OpcodeTokenValue::RET),
+                    "RTI" => TokenValue::Opcode(OpcodeTokenValue::RTI),
                     "ST" => TokenValue::Opcode(OpcodeTokenValue::ST),
                     "STI" => TokenValue::Opcode(OpcodeTokenValue::STI),
                     "STR" => TokenValue::Opcode(OpcodeTokenValue::STR),
                     "TRAP" => TokenValue::Opcode(OpcodeTokenValue::TRAP),
 
-                    "GETC" => TokenValue::TrapRoutine(TrapRoutineTokenValue::GETC),
-                    "OUT" => TokenValue::TrapRoutine(TrapRoutineTokenValue::OUT),
-                    "PUTS" => TokenValue::TrapRoutine(TrapRoutineTokenValue::PUTS),
-                    "IN" => TokenValue::TrapRoutine(TrapRoutineTokenValue::IN),
-                    "PUTSP" => TokenValue::TrapRoutine(TrapRoutineTokenValue::PUTSP),
-                    "HALT" => TokenValue::TrapRoutine(TrapRoutineTokenValue::HALT),
+                    "GETC" => TokenValue::Opcode(OpcodeTokenValue::GETC),
+                    "OUT" => TokenValue::Opcode(OpcodeTokenValue::OUT),
+                    "PUTS" => TokenValue::Opcode(OpcodeTokenValue::PUTS),
+                    "IN" => TokenValue::Opcode(OpcodeTokenValue::IN),
+                    "PUTSP" => TokenValue::Opcode(OpcodeTokenValue::PUTSP),
+                    "HALT" => TokenValue::Opcode(OpcodeTokenValue::HALT),
 
                     "R0" => TokenValue::Register(RegisterTokenValue::R0),
                     "R1" => TokenValue::Register(RegisterTokenValue::R1),
@@ -199,7 +329,15 @@ pub fn analyze(text: &str) -> Vec<Token> {
         start_loc = end_loc;
     }
 
-    return tokens;
+    tokens
+}
+
+/// Rejects literals whose digits do not parse, e.g. `#abc` or `xZZ`.
+fn number_literal_token_value(literal: NumberLiteralTokenValue, text: &str) -> TokenValue {
+    match literal.to_isize() {
+        Ok(_) => TokenValue::NumberLiteral(literal),
+        Err(_) => TokenValue::Error(format!("Invalid number literal `{}`", text)),
+    }
 }
 
 fn get_next_char_while(
@@ -228,3 +366,209 @@ fn get_next_char_while(
         }
     }
 }
+
+/// Reads one character of a string or character literal, decoding escape sequences.
+fn get_next_literal_char(
+    raw_data: &mut Peekable<IntoIter<char>>,
+    end_loc: &mut FileLoc,
+) -> Result<char, String> {
+    let c = raw_data.next().unwrap_or_default();
+    end_loc.col += 1;
+
+    if c != '\\' {
+        return Ok(c);
+    }
+
+    let escape = match raw_data.peek() {
+        Some(&c) if c != '\n' => c,
+        _ => return Err("Incomplete escape sequence".to_string()),
+    };
+    raw_data.next();
+    end_loc.col += 1;
+
+    match escape {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        '0' => Ok('\0'),
+        '\\' | '"' | '\'' => Ok(escape),
+        'x' => {
+            let mut digits = String::new();
+            while digits.len() < 2 {
+                match raw_data.peek() {
+                    Some(&c) if c.is_ascii_hexdigit() => {
+                        digits.push(c);
+                        raw_data.next();
+                        end_loc.col += 1;
+                    }
+                    _ => break,
+                }
+            }
+
+            match u8::from_str_radix(&digits, 16) {
+                Ok(value) if digits.len() == 2 => Ok(value as char),
+                _ => Err("Escape sequence `\\x` must be followed by two hex digits".to_string()),
+            }
+        }
+        _ => Err(format!("Unknown escape sequence `\\{}`", escape)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(text: &str) -> Vec<TokenValue> {
+        analyze(text).into_iter().map(|token| token.value).collect()
+    }
+
+    fn error(text: &str) -> String {
+        match &values(text)[..] {
+            [TokenValue::Error(message)] => message.clone(),
+            values => panic!("expected a single error token, got {:?}", values),
+        }
+    }
+
+    fn literal(text: &str) -> NumberLiteralTokenValue {
+        match &values(text)[..] {
+            [TokenValue::NumberLiteral(literal)] => literal.clone(),
+            values => panic!("expected a single number literal, got {:?}", values),
+        }
+    }
+
+    #[test]
+    fn lexes_number_literals() {
+        for (text, expected) in [
+            ("#10", 10),
+            ("#-10", -10),
+            ("10", 10),
+            ("x1F", 0x1F),
+            ("X1f", 0x1F),
+            ("x-1F", -0x1F),
+            ("0x1F", 0x1F),
+            ("0X1F", 0x1F),
+            ("b0101", 5),
+            ("B1", 1),
+        ] {
+            assert_eq!(literal(text).to_isize(), Ok(expected), "{}", text);
+        }
+
+        assert!(matches!(
+            literal("b0101").format,
+            NumberLiteralFormat::Binary
+        ));
+        assert!(matches!(literal("X3000").format, NumberLiteralFormat::Hex));
+        assert!(matches!(literal("0x3000").format, NumberLiteralFormat::Hex));
+        assert!(matches!(
+            literal("3000").format,
+            NumberLiteralFormat::Decimal
+        ));
+    }
+
+    #[test]
+    fn minus_after_a_literal_is_a_subtraction() {
+        assert!(matches!(
+            &values("x10-1")[..],
+            [
+                TokenValue::NumberLiteral(_),
+                TokenValue::Minus,
+                TokenValue::NumberLiteral(_),
+            ]
+        ));
+    }
+
+    #[test]
+    fn reports_invalid_number_literals() {
+        assert_eq!(error("#abc"), "Invalid number literal `#abc`");
+        assert_eq!(error("x-ZZ"), "Invalid number literal `x-ZZ`");
+        assert_eq!(error("0xZZ"), "Invalid number literal `0xZZ`");
+        assert_eq!(error("12ab"), "Invalid number literal `12ab`");
+    }
+
+    #[test]
+    fn words_that_are_not_literals_are_labels() {
+        for text in ["xcount", "XZ", "x", "b012", "bits"] {
+            assert!(
+                matches!(&values(text)[..], [TokenValue::Label(label)] if label == text),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_string_escapes() {
+        assert!(matches!(
+            &values(r#""a\nb\tc\\d\"e\0f\x41""#)[..],
+            [TokenValue::StringLiteral(s)] if s == "a\nb\tc\\d\"e\0fA"
+        ));
+    }
+
+    #[test]
+    fn stringz_with_embedded_quotes() {
+        assert!(matches!(
+            &values(r#".STRINGZ "say \"hi\"" ; greeting"#)[..],
+            [
+                TokenValue::Directive(DirectiveTokenValue::STRINGZ),
+                TokenValue::StringLiteral(s),
+                TokenValue::Comment(_),
+            ] if s == "say \"hi\""
+        ));
+    }
+
+    #[test]
+    fn decodes_character_literals() {
+        for (text, expected) in [
+            ("'A'", "A"),
+            (r"'\n'", "\n"),
+            (r"'\''", "'"),
+            (r"'\x7F'", "\x7F"),
+        ] {
+            assert!(
+                matches!(
+                    &values(text)[..],
+                    [TokenValue::NumberLiteral(NumberLiteralTokenValue {
+                        format: NumberLiteralFormat::Character,
+                        value,
+                    })] if value == expected
+                ),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn reports_bad_escapes() {
+        assert_eq!(error(r#""\q""#), "Unknown escape sequence `\\q`");
+        assert_eq!(
+            error(r#""\x4""#),
+            "Escape sequence `\\x` must be followed by two hex digits"
+        );
+        // A trailing backslash escapes nothing, so the string is never closed
+        assert_eq!(error("\"abc\\"), "Unterminated string literal");
+    }
+
+    #[test]
+    fn reports_unterminated_strings() {
+        assert_eq!(error("\"abc"), "Unterminated string literal");
+        // The rest of the line is not swallowed by the string
+        assert!(matches!(
+            &values("\"abc\nHALT")[..],
+            [
+                TokenValue::Error(_),
+                TokenValue::NewLine,
+                TokenValue::Opcode(OpcodeTokenValue::HALT),
+            ]
+        ));
+    }
+
+    #[test]
+    fn reports_bad_character_literals() {
+        assert_eq!(error("''"), "Empty character literal");
+        assert_eq!(error("'A"), "Unterminated character literal");
+        assert_eq!(
+            error("'AB'"),
+            "Character literal must contain exactly one character"
+        );
+    }
+}
@@ -1,5 +1,19 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod analysis;
+pub mod assembler;
 pub mod ast;
+pub mod condition;
+pub mod console;
+pub mod dap;
+pub mod debugger;
+pub mod disassembler;
+pub mod grader;
 pub mod lexer;
+pub mod os;
 pub mod parser;
 pub mod passes;
+pub mod repl;
+pub mod simulator;
 mod tokens;
+pub mod trace;
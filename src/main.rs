@@ -1,20 +1,108 @@
 use colored::{Color, Colorize};
+use lc3_language_server::analysis::{
+    self,
+    cfg::{Exit, Target},
+    constants,
+};
+use lc3_language_server::assembler;
 use lc3_language_server::ast::NodeError;
+use lc3_language_server::console::StdConsole;
+use lc3_language_server::dap;
+use lc3_language_server::disassembler::disassemble;
+use lc3_language_server::grader;
 use lc3_language_server::lexer;
 use lc3_language_server::parser;
 use lc3_language_server::passes;
+use lc3_language_server::repl;
+use lc3_language_server::simulator::Machine;
+use lc3_language_server::trace::{self, Tracer};
+use std::io::BufWriter;
+use std::path::Path;
 use std::{env, fs, process};
 
 fn main() {
     let args = env::args().collect::<Vec<String>>();
 
+    if args.contains(&"--dap".to_owned()) {
+        dap::run();
+        return;
+    }
+
+    if args.len() >= 3 && args[1] == "debug" {
+        let file_text = fs::read_to_string(&args[2]).unwrap();
+        repl::run(&file_text, args.contains(&"--os".to_owned()));
+        return;
+    }
+
+    if args.len() >= 3 && args[1] == "test" {
+        let report = match grader::run(Path::new(&args[2])) {
+            Ok(report) => report,
+            Err(error) => {
+                // Kept off stdout, which may be expected to hold only JSON
+                eprintln!("{}: {}", "error".red().bold(), error.bold());
+                process::exit(1);
+            }
+        };
+
+        // Only the JSON is printed when it goes to stdout, so it can be parsed
+        let json_path = args
+            .iter()
+            .position(|arg| arg == "--json")
+            .map(|i| args.get(i + 1));
+        if json_path == Some(None) {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            process::exit(if report.failed > 0 { 1 } else { 0 });
+        }
+
+        for test in &report.tests {
+            if test.passed {
+                println!("{} {}", "PASS".green().bold(), test.name);
+                continue;
+            }
+
+            println!("{} {}", "FAIL".red().bold(), test.name);
+            for failure in &test.failures {
+                println!("    {}", failure.subject.bold());
+                println!("      expected: {}", failure.expected);
+                println!("      actual:   {}", failure.actual);
+            }
+        }
+        println!("\n{} passed, {} failed", report.passed, report.failed);
+
+        if let Some(Some(path)) = json_path {
+            fs::write(path, serde_json::to_string_pretty(&report).unwrap()).unwrap();
+        }
+
+        if report.failed > 0 {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if args.len() >= 4 && args[1] == "trace-diff" {
+        let trace_text = fs::read_to_string(&args[2]).unwrap();
+        let reference_text = fs::read_to_string(&args[3]).unwrap();
+        match trace::diff(&trace_text, &reference_text) {
+            Ok(None) => println!("Traces match"),
+            Ok(Some(report)) => {
+                println!("{}", report);
+                process::exit(1);
+            }
+            Err(error) => {
+                println!("{}: {}", "error".red().bold(), error.bold());
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
     if args.len() < 2 {
         println!("error: Expected file name");
         process::exit(1);
     }
 
     let file_name = &args[1];
-    let file_text = fs::read_to_string(&file_name).unwrap();
+    let file_text = fs::read_to_string(file_name).unwrap();
     let file_lines = file_text.split("\n").collect::<Vec<&str>>();
 
     let tokens = lexer::analyze(&file_text);
@@ -32,32 +120,135 @@ fn main() {
     passes::verify_labels(&mut nodes);
     passes::verify_number_literals_within_range(&mut nodes);
 
+    let program = assembler::assemble(&mut nodes);
+
+    let has_errors = nodes
+        .iter()
+        .flat_map(|node| &node.errors)
+        .any(|error| matches!(error, NodeError::Error(_)));
+    if !has_errors {
+        analysis::analyze(&program, &mut nodes);
+    }
+
+    if args.contains(&"--print-segments".to_owned()) {
+        for segment in &program.segments {
+            println!(
+                "x{:04X}-x{:04X} ({} words)",
+                segment.origin,
+                segment.end().saturating_sub(1),
+                segment.words.len()
+            );
+            for word in &segment.words {
+                println!("  x{:04X}", word);
+            }
+        }
+    }
+
+    if args.contains(&"--print-cfg".to_owned()) {
+        let (cfg, constants) = constants::build_cfg(&program, &nodes);
+        for (i, block) in cfg.blocks.iter().enumerate() {
+            let entry = if cfg.entry == Some(i) { " (entry)" } else { "" };
+            println!("block {}{}", i, entry);
+            for instruction in &block.instructions {
+                // Known values of the registers the instruction reads
+                let known = (instruction.reads().into_iter())
+                    .filter_map(|(register, _)| {
+                        let value = constants.register_before(instruction.address, register)?;
+                        Some(format!("R{} = x{:04X}", register, value))
+                    })
+                    .collect::<Vec<String>>();
+                let known = match known.is_empty() {
+                    true => String::new(),
+                    false => format!("  ; {}", known.join(", ")),
+                };
+                println!(
+                    "  x{:04X}  {}{}",
+                    instruction.address,
+                    disassemble(instruction.word, instruction.address),
+                    known
+                );
+            }
+            for edge in &block.successors {
+                let target = match edge.target {
+                    Target::Block(block) => format!("block {}", block),
+                    Target::Data(address) => format!("data at x{:04X}", address),
+                    Target::Outside(address) => format!("x{:04X}", address),
+                };
+                println!("  -> {} ({:?})", target, edge.kind);
+            }
+            if block.exit != Exit::Edges {
+                println!("  -> {:?}", block.exit);
+            }
+        }
+    }
+
     for node in &nodes {
         for error in &node.errors {
-            let color = match error {
-                NodeError::Error(_) => Color::Red,
-                NodeError::Warning(_) => Color::Yellow,
+            let (color, start_loc, end_loc) = match error {
+                NodeError::Error(_) => (Color::Red, node.start_loc, node.end_loc),
+                NodeError::Warning(_) => (Color::Yellow, node.start_loc, node.end_loc),
+                NodeError::WarningAt(start_loc, end_loc, _) => {
+                    (Color::Yellow, *start_loc, *end_loc)
+                }
             };
 
             match error {
                 NodeError::Error(error) => println!("{}: {}", "error".red().bold(), error.bold()),
-                NodeError::Warning(warning) => {
+                NodeError::Warning(warning) | NodeError::WarningAt(_, _, warning) => {
                     println!("{}: {}", "warning".yellow().bold(), warning.bold())
                 }
             };
 
+            println!("{}:{}:{}\n", file_name, start_loc.line, start_loc.col);
+            println!("\t{}", file_lines[start_loc.line - 1]);
             println!(
-                "{}:{}:{}\n",
-                file_name, node.start_loc.line, node.start_loc.col
+                "\t{}{}",
+                " ".repeat(start_loc.col - 1),
+                "^".repeat(end_loc.col - start_loc.col + 1).color(color)
             );
-            println!("\t{}", file_lines[node.start_loc.line - 1]);
+            println!();
+        }
+    }
+
+    let trace_path = args
+        .iter()
+        .position(|arg| arg == "--trace")
+        .and_then(|i| args.get(i + 1));
+
+    if args.contains(&"--run".to_owned()) || trace_path.is_some() {
+        if has_errors {
             println!(
-                "\t{}{}",
-                " ".repeat(node.start_loc.col - 1),
-                "^".repeat(node.end_loc.col - node.start_loc.col + 1)
-                    .color(color)
+                "{}: {}",
+                "error".red().bold(),
+                "Not running due to errors".bold()
             );
-            println!("");
+            process::exit(1);
+        }
+
+        let mut machine = Machine::new();
+        if args.contains(&"--os".to_owned()) {
+            machine.load_os();
+        }
+        machine.load(&program);
+        if args.contains(&"--user".to_owned()) {
+            machine.enter_user_mode();
+        }
+
+        let mut console = StdConsole::new();
+        let result = match trace_path {
+            Some(path) => {
+                let output = BufWriter::new(fs::File::create(path).unwrap());
+                Tracer::new(&file_text, &program, &nodes, output).run(
+                    &mut machine,
+                    &mut console,
+                    u64::MAX,
+                )
+            }
+            None => machine.run(&mut console, u64::MAX),
+        };
+        if let Err(error) = result {
+            println!("\n{}: {}", "error".red().bold(), error.to_string().bold());
+            process::exit(1);
         }
     }
 }
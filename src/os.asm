@@ -0,0 +1,215 @@
+; LC3 operating system bundled with the simulator.
+;
+; Implements the standard trap routines on top of the memory-mapped keyboard, display and machine
+; control registers. Trap routines run in supervisor mode and return with RTI, so they save every
+; register they use except the ones they return values in. Vector table entries left as zero are
+; pointed at BAD_TRAP and BAD_INTERRUPT when the OS is assembled.
+
+            .ORIG   x0000
+
+TRAP_TABLE  .BLKW   x20
+            .FILL   TRAP_GETC           ; x20
+            .FILL   TRAP_OUT            ; x21
+            .FILL   TRAP_PUTS           ; x22
+            .FILL   TRAP_IN             ; x23
+            .FILL   TRAP_PUTSP          ; x24
+            .FILL   TRAP_HALT           ; x25
+            .BLKW   xDA                 ; x26-xFF
+
+INTERRUPT_TABLE
+            .BLKW   x100                ; x100-x1FF
+
+
+; Reads a character from the keyboard without echoing it.
+;
+; OUT: R0 (character read)
+;
+TRAP_GETC
+            LDI     R0,OS_KBSR          ; wait for a character
+            BRzp    TRAP_GETC
+            LDI     R0,OS_KBDR          ; read it
+            RTI
+
+
+; Writes a character to the display.
+;
+; IN:  R0 (character to write)
+;
+TRAP_OUT
+            ST      R1,OUT_R1           ; save R1
+
+OUT_WAIT
+            LDI     R1,OS_DSR           ; wait for the display
+            BRzp    OUT_WAIT
+            STI     R0,OS_DDR           ; write the character
+
+            LD      R1,OUT_R1           ; restore R1
+            RTI
+
+
+; Writes a null-terminated string with one character per word to the display.
+;
+; IN:  R0 (address of the string)
+;
+TRAP_PUTS
+            ST      R0,PUTS_R0          ; save registers
+            ST      R1,PUTS_R1
+            ST      R2,PUTS_R2
+
+PUTS_LOOP
+            LDR     R1,R0,#0            ; load the next character
+            BRz     PUTS_DONE           ; stop at the null terminator
+
+PUTS_WAIT
+            LDI     R2,OS_DSR           ; wait for the display
+            BRzp    PUTS_WAIT
+            STI     R1,OS_DDR           ; write the character
+
+            ADD     R0,R0,#1            ; move to the next character
+            BRnzp   PUTS_LOOP
+
+PUTS_DONE
+            LD      R0,PUTS_R0          ; restore registers
+            LD      R1,PUTS_R1
+            LD      R2,PUTS_R2
+            RTI
+
+
+; Prompts for a character, reads it from the keyboard and echoes it.
+;
+; OUT: R0 (character read)
+;
+TRAP_IN
+            ST      R7,IN_R7            ; save R7, the nested traps overwrite it
+
+            LEA     R0,IN_PROMPT        ; print the prompt
+            PUTS
+            GETC                        ; read a character
+            OUT                         ; echo it
+
+            LD      R7,IN_R7            ; restore R7
+            RTI
+
+
+; Writes a null-terminated string with two characters per word to the display. The low byte
+; holds the first character of each pair.
+;
+; IN:  R0 (address of the string)
+;
+; Register table:
+; R1: The current word, shifted left while extracting its high byte.
+; R2: The character being written.
+; R3: Display status while waiting for the display.
+; R4: Remaining shifts while extracting the high byte.
+;
+TRAP_PUTSP
+            ST      R0,PUTSP_R0         ; save registers
+            ST      R1,PUTSP_R1
+            ST      R2,PUTSP_R2
+            ST      R3,PUTSP_R3
+            ST      R4,PUTSP_R4
+
+PUTSP_LOOP
+            LDR     R1,R0,#0            ; load the next pair of characters
+            LD      R2,LOW_BYTE_MASK    ; extract the low byte
+            AND     R2,R1,R2
+            BRz     PUTSP_DONE          ; stop at the null terminator
+
+PUTSP_LOW_WAIT
+            LDI     R3,OS_DSR           ; wait for the display
+            BRzp    PUTSP_LOW_WAIT
+            STI     R2,OS_DDR           ; write the low byte
+
+            ; shift the high byte into R2 one bit at a time
+
+            AND     R2,R2,#0
+            AND     R4,R4,#0
+            ADD     R4,R4,#8
+
+PUTSP_SHIFT
+            ADD     R2,R2,R2            ; make room for the next bit
+            ADD     R1,R1,#0            ; copy the top bit of R1 if it is set
+            BRzp    PUTSP_NO_CARRY
+            ADD     R2,R2,#1
+
+PUTSP_NO_CARRY
+            ADD     R1,R1,R1            ; move on to the next bit
+            ADD     R4,R4,#-1
+            BRp     PUTSP_SHIFT
+
+            ADD     R2,R2,#0            ; stop at the null terminator
+            BRz     PUTSP_DONE
+
+PUTSP_HIGH_WAIT
+            LDI     R3,OS_DSR           ; wait for the display
+            BRzp    PUTSP_HIGH_WAIT
+            STI     R2,OS_DDR           ; write the high byte
+
+            ADD     R0,R0,#1            ; move to the next pair
+            BRnzp   PUTSP_LOOP
+
+PUTSP_DONE
+            LD      R0,PUTSP_R0         ; restore registers
+            LD      R1,PUTSP_R1
+            LD      R2,PUTSP_R2
+            LD      R3,PUTSP_R3
+            LD      R4,PUTSP_R4
+            RTI
+
+
+; Prints a message and stops the clock by clearing bit 15 of the MCR.
+;
+TRAP_HALT
+            LEA     R0,HALT_MESSAGE     ; print the halt message
+            PUTS
+
+            LDI     R0,OS_MCR           ; clear the clock enable bit
+            LD      R1,CLOCK_MASK
+            AND     R0,R0,R1
+            STI     R0,OS_MCR
+
+            BRnzp   TRAP_HALT           ; halt again if the clock is restarted
+
+
+; Handles trap vectors without a routine.
+;
+BAD_TRAP
+            LEA     R0,BAD_TRAP_MESSAGE
+            PUTS
+            HALT
+
+
+; Handles interrupt vectors without a handler.
+;
+BAD_INTERRUPT
+            LEA     R0,BAD_INTERRUPT_MESSAGE
+            PUTS
+            HALT
+
+
+OS_KBSR     .FILL   xFE00
+OS_KBDR     .FILL   xFE02
+OS_DSR      .FILL   xFE04
+OS_DDR      .FILL   xFE06
+OS_MCR      .FILL   xFFFE
+
+LOW_BYTE_MASK   .FILL   x00FF
+CLOCK_MASK      .FILL   x7FFF
+
+OUT_R1      .BLKW   #1
+PUTS_R0     .BLKW   #1
+PUTS_R1     .BLKW   #1
+PUTS_R2     .BLKW   #1
+IN_R7       .BLKW   #1
+PUTSP_R0    .BLKW   #1
+PUTSP_R1    .BLKW   #1
+PUTSP_R2    .BLKW   #1
+PUTSP_R3    .BLKW   #1
+PUTSP_R4    .BLKW   #1
+
+IN_PROMPT               .STRINGZ    "\nInput a character> "
+HALT_MESSAGE            .STRINGZ    "\n--- Halting the LC-3 ---\n"
+BAD_TRAP_MESSAGE        .STRINGZ    "\nUnknown trap vector executed\n"
+BAD_INTERRUPT_MESSAGE   .STRINGZ    "\nUnexpected interrupt or exception\n"
+
+            .END
@@ -0,0 +1,42 @@
+//! LC3 operating system image that the simulator can run instead of emulating traps.
+
+use crate::assembler::{self, Program};
+
+const SOURCE: &str = include_str!("os.asm");
+
+/// Assembles the bundled OS, pointing unused trap vectors at `BAD_TRAP` and unused interrupt
+/// vectors at `BAD_INTERRUPT`.
+pub fn assemble() -> Program {
+    let (mut program, _) =
+        assembler::assemble_source(SOURCE).expect("bundled OS failed to assemble");
+
+    let bad_trap = program.symbols["BAD_TRAP"];
+    let bad_interrupt = program.symbols["BAD_INTERRUPT"];
+    let words = &mut program.segments[0].words;
+    for (address, word) in words.iter_mut().enumerate().take(0x200) {
+        if *word == 0 {
+            *word = if address < 0x100 {
+                bad_trap
+            } else {
+                bad_interrupt
+            };
+        }
+    }
+
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_unused_vectors() {
+        let program = assemble();
+        let words = &program.segments[0].words;
+
+        assert_eq!(words[0x25], program.symbols["TRAP_HALT"]);
+        assert_eq!(words[0x30], program.symbols["BAD_TRAP"]);
+        assert_eq!(words[0x180], program.symbols["BAD_INTERRUPT"]);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::ast::{
-    AddAndOpcodeInstructionNodeValue, DirectiveNodeValue, InstructionNodeValue, LiteralOrLabel,
-    Node, NodeError, NodeValue,
+    AddAndOpcodeInstructionNodeValue, DirectiveNodeValue, Expression, InstructionNodeValue,
+    LiteralOrLabel, Node, NodeValue,
 };
 use crate::tokens::{
     DirectiveTokenValue, FileLoc, OpcodeTokenValue, RegisterTokenValue, Token, TokenValue,
@@ -16,74 +16,88 @@ pub fn parse_ast(tokens: &[Token]) -> Vec<Node> {
         idx += 1;
 
         nodes.push(match &token.value {
-            TokenValue::NewLine => Node {
-                value: NodeValue::NewLine,
-                start_loc: token.start_loc,
-                end_loc: token.end_loc,
-                errors: Vec::new(),
-            },
-            TokenValue::Label(label) => Node {
-                value: NodeValue::Label(label.clone()),
-                start_loc: token.start_loc,
-                end_loc: token.end_loc,
-                errors: Vec::new(),
-            },
-            TokenValue::Comment(comment) => Node {
-                value: NodeValue::Comment(comment.clone()),
-                start_loc: token.start_loc,
-                end_loc: token.end_loc,
-                errors: Vec::new(),
-            },
+            TokenValue::NewLine => Node::new(NodeValue::NewLine, token.start_loc, token.end_loc),
+            TokenValue::Label(label) => Node::new(
+                NodeValue::Label(label.clone()),
+                token.start_loc,
+                token.end_loc,
+            ),
+            TokenValue::Comment(comment) => Node::new(
+                NodeValue::Comment(comment.clone()),
+                token.start_loc,
+                token.end_loc,
+            ),
             TokenValue::Opcode(opcode) => {
                 let (args, end_loc) =
-                    get_args(&tokens, &mut idx).unwrap_or((Vec::new(), token.end_loc));
+                    get_args(tokens, &mut idx).unwrap_or((Vec::new(), token.end_loc));
 
-                match parse_instruction_node(*opcode, &args, token.start_loc, token.end_loc) {
-                    Ok(node) => node,
-                    Err(msg) => Node {
-                        value: NodeValue::Instruction(InstructionNodeValue::Error {
+                let result = match find_token_error(&args) {
+                    Some(msg) => Err(msg),
+                    None => parse_instruction_node(*opcode, &args, token.start_loc, token.end_loc)
+                        .map_err(|msg| (msg.to_string(), token.start_loc, end_loc)),
+                };
+
+                match result {
+                    Ok(mut node) => {
+                        node.operand_locs = (args.iter())
+                            .map(|arg| (arg.start_loc, arg.end_loc))
+                            .collect();
+                        node
+                    }
+                    Err((msg, start_loc, end_loc)) => Node::error(
+                        NodeValue::Instruction(InstructionNodeValue::Error {
                             opcode: *opcode,
                             args: Some(args),
                         }),
-                        start_loc: token.start_loc,
+                        start_loc,
                         end_loc,
-                        errors: vec![NodeError::Error(msg.to_string())],
-                    },
+                        msg,
+                    ),
                 }
             }
             TokenValue::Directive(directive) => {
                 let (args, end_loc) =
-                    get_args(&tokens, &mut idx).unwrap_or((Vec::new(), token.end_loc));
-                match parse_directive_node(directive.clone(), &args, token.start_loc, token.end_loc)
-                {
+                    get_args(tokens, &mut idx).unwrap_or((Vec::new(), token.end_loc));
+                let result = match find_token_error(&args) {
+                    Some(msg) => Err(msg),
+                    None => parse_directive_node(
+                        directive.clone(),
+                        &args,
+                        token.start_loc,
+                        token.end_loc,
+                    )
+                    .map_err(|msg| (msg, token.start_loc, end_loc)),
+                };
+
+                match result {
                     Ok(node) => node,
-                    Err(msg) => Node {
-                        value: NodeValue::Directive(DirectiveNodeValue::Error {
+                    Err((msg, start_loc, end_loc)) => Node::error(
+                        NodeValue::Directive(DirectiveNodeValue::Error {
                             directive: directive.clone(),
                             args: Some(args),
                         }),
-                        start_loc: token.start_loc,
+                        start_loc,
                         end_loc,
-                        errors: vec![NodeError::Error(msg.to_string())],
-                    },
+                        msg,
+                    ),
                 }
             }
-            TokenValue::TrapRoutine(routine) => Node {
-                value: NodeValue::TrapRoutine(*routine),
-                start_loc: token.start_loc,
-                end_loc: token.end_loc,
-                errors: Vec::new(),
-            },
-            _ => Node {
-                value: NodeValue::UnexpectedToken(token.clone()),
-                start_loc: token.start_loc,
-                end_loc: token.end_loc,
-                errors: vec![NodeError::Error("Unexpected token".to_string())],
-            },
+            TokenValue::Error(msg) => Node::error(
+                NodeValue::UnexpectedToken(token.clone()),
+                token.start_loc,
+                token.end_loc,
+                msg.clone(),
+            ),
+            _ => Node::error(
+                NodeValue::UnexpectedToken(token.clone()),
+                token.start_loc,
+                token.end_loc,
+                "Unexpected token".to_string(),
+            ),
         });
     }
 
-    return nodes;
+    nodes
 }
 
 fn parse_instruction_node(
@@ -94,111 +108,114 @@ fn parse_instruction_node(
 ) -> Result<Node, &'static str> {
     match opcode {
         OpcodeTokenValue::ADD => {
-            let value = parse_and_add_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::ADD(value)),
-                start_loc: token_start_loc,
-                end_loc: args[2].end_loc,
-                errors: Vec::new(),
-            })
+            let value = parse_and_add_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::ADD(value)),
+                token_start_loc,
+                args[2].end_loc,
+            ))
         }
         OpcodeTokenValue::AND => {
-            let value = parse_and_add_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::AND(value)),
-                start_loc: token_start_loc,
-                end_loc: args[2].end_loc,
-                errors: Vec::new(),
-            })
+            let value = parse_and_add_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::AND(value)),
+                token_start_loc,
+                args[2].end_loc,
+            ))
         }
         OpcodeTokenValue::BR { n, z, p } => {
-            let value = parse_br_jsr_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::BR {
+            let value = parse_br_jsr_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::BR {
                     n,
                     z,
                     p,
                     pc_offset9: value,
                 }),
-                start_loc: token_start_loc,
-                end_loc: args.last().unwrap().end_loc,
-                errors: Vec::new(),
-            })
+                token_start_loc,
+                args.last().unwrap().end_loc,
+            ))
         }
         OpcodeTokenValue::JMP => {
             if args.len() != 1 {
                 Err("Incorrect number of arguments (expected 1)")
             } else {
                 match &args[0].value {
-                    TokenValue::Register(register) => Ok(Node {
-                        value: NodeValue::Instruction(InstructionNodeValue::JMP {
-                            base_r: *register,
-                        }),
-                        start_loc: token_start_loc,
-                        end_loc: args.last().unwrap().end_loc,
-                        errors: Vec::new(),
-                    }),
+                    TokenValue::Register(register) => Ok(Node::new(
+                        NodeValue::Instruction(InstructionNodeValue::JMP { base_r: *register }),
+                        token_start_loc,
+                        args.last().unwrap().end_loc,
+                    )),
                     _ => Err("Incorrect argument type (expected register)"),
                 }
             }
         }
         OpcodeTokenValue::JSR => {
-            let value = parse_br_jsr_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::JSR { pc_offset11: value }),
-                start_loc: token_start_loc,
-                end_loc: args.last().unwrap().end_loc,
-                errors: Vec::new(),
-            })
+            let value = parse_br_jsr_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::JSR { pc_offset11: value }),
+                token_start_loc,
+                args.last().unwrap().end_loc,
+            ))
+        }
+        OpcodeTokenValue::JSRR => {
+            if args.len() != 1 {
+                Err("Incorrect number of arguments (expected 1)")
+            } else {
+                match &args[0].value {
+                    TokenValue::Register(register) => Ok(Node::new(
+                        NodeValue::Instruction(InstructionNodeValue::JSRR { base_r: *register }),
+                        token_start_loc,
+                        args.last().unwrap().end_loc,
+                    )),
+                    _ => Err("Incorrect argument type (expected register)"),
+                }
+            }
         }
         OpcodeTokenValue::LD => {
-            let value = parse_ld_ldi_lea_st_sti_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::LD {
+            let value = parse_ld_ldi_lea_st_sti_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::LD {
                     dr: value.0,
                     pc_offset9: value.1,
                 }),
-                start_loc: token_start_loc,
-                end_loc: args.last().unwrap().end_loc,
-                errors: Vec::new(),
-            })
+                token_start_loc,
+                args.last().unwrap().end_loc,
+            ))
         }
         OpcodeTokenValue::LDI => {
-            let value = parse_ld_ldi_lea_st_sti_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::LDI {
+            let value = parse_ld_ldi_lea_st_sti_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::LDI {
                     dr: value.0,
                     pc_offset9: value.1,
                 }),
-                start_loc: token_start_loc,
-                end_loc: token_end_loc,
-                errors: Vec::new(),
-            })
+                token_start_loc,
+                token_end_loc,
+            ))
         }
         OpcodeTokenValue::LDR => {
-            let value = parse_ldr_str_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::LDR {
+            let value = parse_ldr_str_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::LDR {
                     dr: value.0,
                     base_r: value.1,
                     offset6: value.2,
                 }),
-                start_loc: token_start_loc,
-                end_loc: args.last().unwrap().end_loc,
-                errors: Vec::new(),
-            })
+                token_start_loc,
+                args.last().unwrap().end_loc,
+            ))
         }
         OpcodeTokenValue::LEA => {
-            let value = parse_ld_ldi_lea_st_sti_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::LEA {
+            let value = parse_ld_ldi_lea_st_sti_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::LEA {
                     dr: value.0,
                     pc_offset9: value.1,
                 }),
-                start_loc: token_start_loc,
-                end_loc: args.last().unwrap().end_loc,
-                errors: Vec::new(),
-            })
+                token_start_loc,
+                args.last().unwrap().end_loc,
+            ))
         }
         OpcodeTokenValue::NOT => {
             if args.len() != 2 {
@@ -207,89 +224,134 @@ fn parse_instruction_node(
 
             if let TokenValue::Register(dr) = &args[0].value {
                 if let TokenValue::Register(sr) = &args[1].value {
-                    return Ok(Node {
-                        value: NodeValue::Instruction(InstructionNodeValue::NOT {
-                            dr: *dr,
-                            sr: *sr,
-                        }),
-                        start_loc: token_start_loc,
-                        end_loc: args.last().unwrap().end_loc,
-                        errors: Vec::new(),
-                    });
+                    return Ok(Node::new(
+                        NodeValue::Instruction(InstructionNodeValue::NOT { dr: *dr, sr: *sr }),
+                        token_start_loc,
+                        args.last().unwrap().end_loc,
+                    ));
                 }
             }
 
-            return Err("Incorrect argument types (expected register, register)");
-        }
-        OpcodeTokenValue::RET => {
-            if args.len() != 0 {
-                Err("Incorrect number of arguments (expected 0)")
-            } else {
-                Ok(Node {
-                    value: NodeValue::Instruction(InstructionNodeValue::RET),
-                    start_loc: token_start_loc,
-                    end_loc: token_end_loc,
-                    errors: Vec::new(),
-                })
-            }
+            Err("Incorrect argument types (expected register, register)")
         }
+        OpcodeTokenValue::RET => parse_no_args_instruction_node(
+            InstructionNodeValue::RET,
+            args,
+            token_start_loc,
+            token_end_loc,
+        ),
+        OpcodeTokenValue::RTI => parse_no_args_instruction_node(
+            InstructionNodeValue::RTI,
+            args,
+            token_start_loc,
+            token_end_loc,
+        ),
         OpcodeTokenValue::ST => {
-            let value = parse_ld_ldi_lea_st_sti_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::ST {
+            let value = parse_ld_ldi_lea_st_sti_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::ST {
                     sr: value.0,
                     pc_offset9: value.1,
                 }),
-                start_loc: token_start_loc,
-                end_loc: args.last().unwrap().end_loc,
-                errors: Vec::new(),
-            })
+                token_start_loc,
+                args.last().unwrap().end_loc,
+            ))
         }
         OpcodeTokenValue::STI => {
-            let value = parse_ld_ldi_lea_st_sti_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::STI {
+            let value = parse_ld_ldi_lea_st_sti_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::STI {
                     sr: value.0,
                     pc_offset9: value.1,
                 }),
-                start_loc: token_start_loc,
-                end_loc: args.last().unwrap().end_loc,
-                errors: Vec::new(),
-            })
+                token_start_loc,
+                args.last().unwrap().end_loc,
+            ))
         }
         OpcodeTokenValue::STR => {
-            let value = parse_ldr_str_args(&args)?;
-            Ok(Node {
-                value: NodeValue::Instruction(InstructionNodeValue::LDR {
-                    dr: value.0,
+            let value = parse_ldr_str_args(args)?;
+            Ok(Node::new(
+                NodeValue::Instruction(InstructionNodeValue::STR {
+                    sr: value.0,
                     base_r: value.1,
                     offset6: value.2,
                 }),
-                start_loc: token_start_loc,
-                end_loc: args.last().unwrap().end_loc,
-                errors: Vec::new(),
-            })
+                token_start_loc,
+                args.last().unwrap().end_loc,
+            ))
         }
         OpcodeTokenValue::TRAP => {
             if args.len() != 1 {
                 Err("Incorrect number of arguments (expected 1)")
             } else {
                 match &args[0].value {
-                    TokenValue::NumberLiteral(literal) => Ok(Node {
-                        value: NodeValue::Instruction(InstructionNodeValue::TRAP {
+                    TokenValue::NumberLiteral(literal) => Ok(Node::new(
+                        NodeValue::Instruction(InstructionNodeValue::TRAP {
                             trapvect8: literal.clone(),
                         }),
-                        start_loc: token_start_loc,
-                        end_loc: args.last().unwrap().end_loc,
-                        errors: Vec::new(),
-                    }),
+                        token_start_loc,
+                        args.last().unwrap().end_loc,
+                    )),
                     _ => Err("Incorrect argument type (expected literal)"),
                 }
             }
         }
+        OpcodeTokenValue::GETC => parse_no_args_instruction_node(
+            InstructionNodeValue::GETC,
+            args,
+            token_start_loc,
+            token_end_loc,
+        ),
+        OpcodeTokenValue::OUT => parse_no_args_instruction_node(
+            InstructionNodeValue::OUT,
+            args,
+            token_start_loc,
+            token_end_loc,
+        ),
+        OpcodeTokenValue::PUTS => parse_no_args_instruction_node(
+            InstructionNodeValue::PUTS,
+            args,
+            token_start_loc,
+            token_end_loc,
+        ),
+        OpcodeTokenValue::IN => parse_no_args_instruction_node(
+            InstructionNodeValue::IN,
+            args,
+            token_start_loc,
+            token_end_loc,
+        ),
+        OpcodeTokenValue::PUTSP => parse_no_args_instruction_node(
+            InstructionNodeValue::PUTSP,
+            args,
+            token_start_loc,
+            token_end_loc,
+        ),
+        OpcodeTokenValue::HALT => parse_no_args_instruction_node(
+            InstructionNodeValue::HALT,
+            args,
+            token_start_loc,
+            token_end_loc,
+        ),
     }
 }
 
+fn parse_no_args_instruction_node(
+    value: InstructionNodeValue,
+    args: &[Token],
+    token_start_loc: FileLoc,
+    token_end_loc: FileLoc,
+) -> Result<Node, &'static str> {
+    if !args.is_empty() {
+        return Err("Incorrect number of arguments (expected 0)");
+    }
+
+    Ok(Node::new(
+        NodeValue::Instruction(value),
+        token_start_loc,
+        token_end_loc,
+    ))
+}
+
 fn parse_and_add_args(args: &[Token]) -> Result<AddAndOpcodeInstructionNodeValue, &'static str> {
     if args.len() != 3 {
         return Err("Incorrect number of arguments (expected 3)");
@@ -317,7 +379,7 @@ fn parse_and_add_args(args: &[Token]) -> Result<AddAndOpcodeInstructionNodeValue
         }
     }
 
-    return Err("Incorrect argument types (expected register, register, register/literal)");
+    Err("Incorrect argument types (expected register, register, register/literal)")
 }
 
 fn parse_br_jsr_args(args: &[Token]) -> Result<LiteralOrLabel, &'static str> {
@@ -325,11 +387,11 @@ fn parse_br_jsr_args(args: &[Token]) -> Result<LiteralOrLabel, &'static str> {
         return Err("Incorrect number of arguments (expected 1)");
     }
 
-    return match &args[0].value {
+    match &args[0].value {
         TokenValue::NumberLiteral(literal) => Ok(LiteralOrLabel::Literal(literal.clone())),
         TokenValue::Label(label) => Ok(LiteralOrLabel::Label(label.clone())),
         _ => Err("Expected literal or label"),
-    };
+    }
 }
 
 fn parse_ld_ldi_lea_st_sti_args(
@@ -349,7 +411,7 @@ fn parse_ld_ldi_lea_st_sti_args(
         };
     }
 
-    return Err("Incorrect argument types (expected register, literal/label)");
+    Err("Incorrect argument types (expected register, literal/label)")
 }
 
 fn parse_ldr_str_args(
@@ -373,7 +435,39 @@ fn parse_ldr_str_args(
         }
     }
 
-    return Err("Incorrect argument types (expected register, register, literal/label)");
+    Err("Incorrect argument types (expected register, register, literal/label)")
+}
+
+/// Parses `operand ((+|-) operand)*` where each operand is a literal or label.
+fn parse_expression(args: &[Token]) -> Result<Expression, String> {
+    if args.is_empty() {
+        return Err("Incorrect number of arguments (expected 1)".to_string());
+    }
+
+    let mut expression = Expression::Operand(parse_expression_operand(&args[0])?);
+
+    for pair in args[1..].chunks(2) {
+        let [operator, operand] = pair else {
+            return Err("Expected literal or label after operator".to_string());
+        };
+        let operand = Box::new(Expression::Operand(parse_expression_operand(operand)?));
+
+        expression = match operator.value {
+            TokenValue::Plus => Expression::Add(Box::new(expression), operand),
+            TokenValue::Minus => Expression::Subtract(Box::new(expression), operand),
+            _ => return Err("Incorrect number of arguments (expected 1)".to_string()),
+        };
+    }
+
+    Ok(expression)
+}
+
+fn parse_expression_operand(arg: &Token) -> Result<LiteralOrLabel, String> {
+    match &arg.value {
+        TokenValue::NumberLiteral(literal) => Ok(LiteralOrLabel::Literal(literal.clone())),
+        TokenValue::Label(label) => Ok(LiteralOrLabel::Label(label.clone())),
+        _ => Err("Incorrect argument type (expected literal or label)".to_string()),
+    }
 }
 
 fn parse_directive_node(
@@ -388,77 +482,69 @@ fn parse_directive_node(
                 Err("Incorrect number of arguments (expected 1)".to_string())
             } else {
                 match &args[0].value {
-                    TokenValue::NumberLiteral(literal) => Ok(Node {
-                        value: NodeValue::Directive(DirectiveNodeValue::ORIG(literal.clone())),
-                        start_loc: token_start_loc,
-                        end_loc: token_end_loc,
-                        errors: Vec::new(),
-                    }),
+                    TokenValue::NumberLiteral(literal) => Ok(Node::new(
+                        NodeValue::Directive(DirectiveNodeValue::ORIG(literal.clone())),
+                        token_start_loc,
+                        token_end_loc,
+                    )),
                     _ => Err("Incorrect argument type (expected literal)".to_string()),
                 }
             }
         }
         DirectiveTokenValue::FILL => {
-            if args.len() != 1 {
-                Err("Incorrect number of arguments (expected 1)".to_string())
-            } else {
-                match &args[0].value {
-                    TokenValue::NumberLiteral(literal) => Ok(Node {
-                        value: NodeValue::Directive(DirectiveNodeValue::FILL(literal.clone())),
-                        start_loc: token_start_loc,
-                        end_loc: token_end_loc,
-                        errors: Vec::new(),
-                    }),
-                    _ => Err("Incorrect argument type (expected literal)".to_string()),
-                }
-            }
+            let expression = parse_expression(args)?;
+            Ok(Node::new(
+                NodeValue::Directive(DirectiveNodeValue::FILL(expression)),
+                token_start_loc,
+                token_end_loc,
+            ))
         }
         DirectiveTokenValue::BLKW => {
-            if args.len() != 1 {
-                Err("Incorrect number of arguments (expected 1)".to_string())
-            } else {
-                match &args[0].value {
-                    TokenValue::NumberLiteral(literal) => Ok(Node {
-                        value: NodeValue::Directive(DirectiveNodeValue::BLKW(literal.clone())),
-                        start_loc: token_start_loc,
-                        end_loc: token_end_loc,
-                        errors: Vec::new(),
-                    }),
-                    _ => Err("Incorrect argument type (expected literal)".to_string()),
-                }
-            }
+            let expression = parse_expression(args)?;
+            Ok(Node::new(
+                NodeValue::Directive(DirectiveNodeValue::BLKW(expression)),
+                token_start_loc,
+                token_end_loc,
+            ))
         }
         DirectiveTokenValue::STRINGZ => {
             if args.len() != 1 {
                 Err("Incorrect number of arguments (expected 1)".to_string())
             } else {
                 match &args[0].value {
-                    TokenValue::StringLiteral(literal) => Ok(Node {
-                        value: NodeValue::Directive(DirectiveNodeValue::STRINGZ(literal.clone())),
-                        start_loc: token_start_loc,
-                        end_loc: token_end_loc,
-                        errors: Vec::new(),
-                    }),
+                    TokenValue::StringLiteral(literal) => Ok(Node::new(
+                        NodeValue::Directive(DirectiveNodeValue::STRINGZ(literal.clone())),
+                        token_start_loc,
+                        token_end_loc,
+                    )),
                     _ => Err("Incorrect argument type (expected literal)".to_string()),
                 }
             }
         }
         DirectiveTokenValue::END => {
-            if args.len() != 0 {
+            if !args.is_empty() {
                 Err("Incorrect number of arguments (expected 0)".to_string())
             } else {
-                Ok(Node {
-                    value: NodeValue::Directive(DirectiveNodeValue::END),
-                    start_loc: token_start_loc,
-                    end_loc: token_end_loc,
-                    errors: Vec::new(),
-                })
+                Ok(Node::new(
+                    NodeValue::Directive(DirectiveNodeValue::END),
+                    token_start_loc,
+                    token_end_loc,
+                ))
             }
         }
         DirectiveTokenValue::Error(error) => Err(format!("Unknown directive {}", &error)),
     }
 }
 
+/// Lexical errors in the arguments take precedence over any argument checks.
+/// Finds an argument the lexer rejected, with its location so only that token is reported.
+fn find_token_error(args: &[Token]) -> Option<(String, FileLoc, FileLoc)> {
+    args.iter().find_map(|arg| match &arg.value {
+        TokenValue::Error(msg) => Some((msg.clone(), arg.start_loc, arg.end_loc)),
+        _ => None,
+    })
+}
+
 fn get_args(tokens: &[Token], idx: &mut usize) -> Option<(Vec<Token>, FileLoc)> {
     let mut args = Vec::<Token>::new();
     let mut end_loc = None;
@@ -469,7 +555,10 @@ fn get_args(tokens: &[Token], idx: &mut usize) -> Option<(Vec<Token>, FileLoc)>
             TokenValue::Register(_)
             | TokenValue::Label(_)
             | TokenValue::NumberLiteral(_)
-            | TokenValue::StringLiteral(_) => {
+            | TokenValue::StringLiteral(_)
+            | TokenValue::Error(_)
+            | TokenValue::Plus
+            | TokenValue::Minus => {
                 args.push(token.clone());
                 end_loc = Some(token.end_loc);
                 *idx += 1;
@@ -479,19 +568,19 @@ fn get_args(tokens: &[Token], idx: &mut usize) -> Option<(Vec<Token>, FileLoc)>
             }
         }
 
-        let token = &tokens[*idx];
-        match token.value {
-            TokenValue::Comma => {
+        // Operators join their operands without commas
+        let is_operator = matches!(token.value, TokenValue::Plus | TokenValue::Minus);
+        match tokens.get(*idx).map(|token| &token.value) {
+            Some(TokenValue::Comma) => {
                 *idx += 1;
             }
+            Some(TokenValue::Plus | TokenValue::Minus) => {}
+            _ if is_operator => {}
             _ => {
                 break;
             }
         }
     }
 
-    match end_loc {
-        Some(end_loc) => Some((args, end_loc)),
-        None => None,
-    }
+    end_loc.map(|end_loc| (args, end_loc))
 }
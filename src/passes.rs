@@ -1,6 +1,6 @@
 use crate::ast::{
-    AddAndOpcodeInstructionNodeValue, InstructionNodeValue, LiteralOrLabel, Node, NodeError,
-    NodeValue,
+    AddAndOpcodeInstructionNodeValue, DirectiveNodeValue, Expression, InstructionNodeValue,
+    LiteralOrLabel, Node, NodeError, NodeValue,
 };
 use crate::tokens::{NumberLiteralFormat, NumberLiteralTokenValue};
 
@@ -8,62 +8,33 @@ pub fn verify_labels(ast: &mut [Node]) {
     let mut labels = Vec::<String>::new();
 
     for node in &*ast {
-        match &node.value {
-            NodeValue::Label(label) => {
-                labels.push(label.clone());
-            }
-            _ => {}
+        if let NodeValue::Label(label) = &node.value {
+            labels.push(label.clone());
         }
     }
 
-    let mut usages = Vec::<(&mut Node, LiteralOrLabel)>::new();
     for node in &mut *ast {
-        match node.value.clone() {
+        let operands = match node.value.clone() {
             NodeValue::Instruction(instruction) => match instruction {
-                InstructionNodeValue::BR {
-                    n: _,
-                    z: _,
-                    p: _,
-                    pc_offset9,
-                } => usages.push((node, pc_offset9.clone())),
-                InstructionNodeValue::JSR { pc_offset11 } => {
-                    usages.push((node, pc_offset11.clone()))
-                }
-                InstructionNodeValue::LD { dr: _, pc_offset9 } => {
-                    usages.push((node, pc_offset9.clone()))
-                }
-                InstructionNodeValue::LDI { dr: _, pc_offset9 } => {
-                    usages.push((node, pc_offset9.clone()))
-                }
-                InstructionNodeValue::LDR {
-                    dr: _,
-                    base_r: _,
-                    offset6,
-                } => usages.push((node, offset6.clone())),
-                InstructionNodeValue::LEA { dr: _, pc_offset9 } => {
-                    usages.push((node, pc_offset9.clone()))
-                }
-                InstructionNodeValue::ST { sr: _, pc_offset9 } => {
-                    usages.push((node, pc_offset9.clone()))
-                }
-                InstructionNodeValue::STI { sr: _, pc_offset9 } => {
-                    usages.push((node, pc_offset9.clone()))
-                }
-                InstructionNodeValue::STR {
-                    sr: _,
-                    base_r: _,
-                    offset6,
-                } => usages.push((node, offset6.clone())),
-                _ => {}
+                InstructionNodeValue::BR { pc_offset9, .. }
+                | InstructionNodeValue::LD { pc_offset9, .. }
+                | InstructionNodeValue::LDI { pc_offset9, .. }
+                | InstructionNodeValue::LEA { pc_offset9, .. }
+                | InstructionNodeValue::ST { pc_offset9, .. }
+                | InstructionNodeValue::STI { pc_offset9, .. } => vec![pc_offset9],
+                InstructionNodeValue::JSR { pc_offset11 } => vec![pc_offset11],
+                InstructionNodeValue::LDR { offset6, .. }
+                | InstructionNodeValue::STR { offset6, .. } => vec![offset6],
+                _ => Vec::new(),
             },
-            _ => {}
-        }
-    }
+            NodeValue::Directive(
+                DirectiveNodeValue::FILL(expression) | DirectiveNodeValue::BLKW(expression),
+            ) => expression_operands(&expression),
+            _ => Vec::new(),
+        };
 
-    for (node, literal_or_label) in usages {
-        match literal_or_label {
-            LiteralOrLabel::Literal(_) => {}
-            LiteralOrLabel::Label(label) => {
+        for operand in operands {
+            if let LiteralOrLabel::Label(label) = operand {
                 if !labels.contains(&label) {
                     node.errors
                         .push(NodeError::Error(format!("Undefined label `{}`", label)));
@@ -73,99 +44,67 @@ pub fn verify_labels(ast: &mut [Node]) {
     }
 }
 
+fn expression_operands(expression: &Expression) -> Vec<LiteralOrLabel> {
+    match expression {
+        Expression::Operand(operand) => vec![operand.clone()],
+        Expression::Add(lhs, rhs) | Expression::Subtract(lhs, rhs) => {
+            let mut operands = expression_operands(lhs);
+            operands.extend(expression_operands(rhs));
+            operands
+        }
+    }
+}
+
 pub fn verify_number_literals_within_range(ast: &mut [Node]) {
     for node in &mut *ast {
-        match node.value.clone() {
-            NodeValue::Instruction(instruction) => match instruction {
-                InstructionNodeValue::ADD(v) => match v {
-                    AddAndOpcodeInstructionNodeValue::IMM {
-                        dr: _,
-                        sr1: _,
-                        imm5,
-                    } => verify_literal_within_range(node, imm5, true, 5),
-                    _ => {}
-                },
-                InstructionNodeValue::AND(v) => match v {
-                    AddAndOpcodeInstructionNodeValue::IMM {
-                        dr: _,
-                        sr1: _,
-                        imm5,
-                    } => verify_literal_within_range(node, imm5, true, 5),
-                    _ => {}
-                },
+        if let NodeValue::Instruction(instruction) = node.value.clone() {
+            match instruction {
+                InstructionNodeValue::ADD(AddAndOpcodeInstructionNodeValue::IMM {
+                    imm5, ..
+                })
+                | InstructionNodeValue::AND(AddAndOpcodeInstructionNodeValue::IMM {
+                    imm5, ..
+                }) => verify_literal_within_range(node, imm5, true, 5),
                 InstructionNodeValue::BR {
-                    n: _,
-                    z: _,
-                    p: _,
-                    pc_offset9,
-                } => match pc_offset9 {
-                    LiteralOrLabel::Literal(literal) => {
-                        verify_literal_within_range(node, literal, true, 9)
-                    }
-                    _ => {}
-                },
-                InstructionNodeValue::JSR { pc_offset11 } => match pc_offset11 {
-                    LiteralOrLabel::Literal(literal) => {
-                        verify_literal_within_range(node, literal, true, 11)
-                    }
-                    _ => {}
-                },
+                    pc_offset9: LiteralOrLabel::Literal(literal),
+                    ..
+                }
+                | InstructionNodeValue::LD {
+                    pc_offset9: LiteralOrLabel::Literal(literal),
+                    ..
+                }
+                | InstructionNodeValue::LDI {
+                    pc_offset9: LiteralOrLabel::Literal(literal),
+                    ..
+                }
+                | InstructionNodeValue::LEA {
+                    pc_offset9: LiteralOrLabel::Literal(literal),
+                    ..
+                }
+                | InstructionNodeValue::ST {
+                    pc_offset9: LiteralOrLabel::Literal(literal),
+                    ..
+                }
+                | InstructionNodeValue::STI {
+                    pc_offset9: LiteralOrLabel::Literal(literal),
+                    ..
+                } => verify_literal_within_range(node, literal, true, 9),
+                InstructionNodeValue::JSR {
+                    pc_offset11: LiteralOrLabel::Literal(literal),
+                } => verify_literal_within_range(node, literal, true, 11),
+                InstructionNodeValue::LDR {
+                    offset6: LiteralOrLabel::Literal(literal),
+                    ..
+                }
+                | InstructionNodeValue::STR {
+                    offset6: LiteralOrLabel::Literal(literal),
+                    ..
+                } => verify_literal_within_range(node, literal, true, 6),
                 InstructionNodeValue::TRAP { trapvect8 } => {
                     verify_literal_within_range(node, trapvect8, false, 8)
                 }
-                InstructionNodeValue::LD { dr: _, pc_offset9 } => match pc_offset9 {
-                    LiteralOrLabel::Literal(literal) => {
-                        verify_literal_within_range(node, literal, true, 9)
-                    }
-                    _ => {}
-                },
-                InstructionNodeValue::LDI { dr: _, pc_offset9 } => match pc_offset9 {
-                    LiteralOrLabel::Literal(literal) => {
-                        verify_literal_within_range(node, literal, true, 9)
-                    }
-                    _ => {}
-                },
-                InstructionNodeValue::LDR {
-                    dr: _,
-                    base_r: _,
-                    offset6,
-                } => match offset6 {
-                    LiteralOrLabel::Literal(literal) => {
-                        verify_literal_within_range(node, literal, true, 6)
-                    }
-                    _ => {}
-                },
-                InstructionNodeValue::LEA { dr: _, pc_offset9 } => match pc_offset9 {
-                    LiteralOrLabel::Literal(literal) => {
-                        verify_literal_within_range(node, literal, true, 9)
-                    }
-                    _ => {}
-                },
-                InstructionNodeValue::ST { sr: _, pc_offset9 } => match pc_offset9 {
-                    LiteralOrLabel::Literal(literal) => {
-                        verify_literal_within_range(node, literal, true, 9)
-                    }
-                    _ => {}
-                },
-                InstructionNodeValue::STI { sr: _, pc_offset9 } => match pc_offset9 {
-                    LiteralOrLabel::Literal(literal) => {
-                        verify_literal_within_range(node, literal, true, 9)
-                    }
-                    _ => {}
-                },
-                InstructionNodeValue::STR {
-                    sr: _,
-                    base_r: _,
-                    offset6,
-                } => match offset6 {
-                    LiteralOrLabel::Literal(literal) => {
-                        verify_literal_within_range(node, literal, true, 6)
-                    }
-                    _ => {}
-                },
                 _ => {}
-            },
-            _ => {}
+            }
         }
     }
 }
@@ -176,11 +115,13 @@ fn verify_literal_within_range(
     sign_extend: bool,
     bits: u32,
 ) {
-    let value = match literal.format {
-        NumberLiteralFormat::Hex => isize::from_str_radix(&literal.value, 16),
-        NumberLiteralFormat::Decimal => isize::from_str_radix(&literal.value, 10),
-    }
-    .unwrap();
+    let Ok(value) = literal.to_isize() else {
+        node.errors.push(NodeError::Error(format!(
+            "Invalid number literal `{}`",
+            literal
+        )));
+        return;
+    };
 
     let min_value = if sign_extend {
         -(2_isize.pow(bits - 1))
@@ -196,27 +137,31 @@ fn verify_literal_within_range(
 
     if value < min_value || value > max_value {
         node.errors.push(NodeError::Warning(format!(
-            "Number literal `{}{}` is out of range. Must be within [{}, {}] ({} bits {})",
-            match literal.format {
-                NumberLiteralFormat::Hex => "x",
-                NumberLiteralFormat::Decimal => "#",
-            },
-            literal.value,
+            "Number literal `{}` is out of range. Must be within [{}, {}] ({} bits {})",
+            literal,
             match literal.format {
                 NumberLiteralFormat::Hex => format!(
                     "x{}{:X}",
                     if min_value < 0 { "-" } else { "" },
                     min_value.abs()
                 ),
-                NumberLiteralFormat::Decimal => format!("#{}", min_value),
+                NumberLiteralFormat::Binary
+                | NumberLiteralFormat::Decimal
+                | NumberLiteralFormat::Character => {
+                    format!("#{}", min_value)
+                }
             },
             match literal.format {
                 NumberLiteralFormat::Hex => format!(
                     "x{}{:X}",
-                    if min_value < 0 { "-" } else { "" },
+                    if max_value < 0 { "-" } else { "" },
                     max_value.abs()
                 ),
-                NumberLiteralFormat::Decimal => format!("#{}", max_value),
+                NumberLiteralFormat::Binary
+                | NumberLiteralFormat::Decimal
+                | NumberLiteralFormat::Character => {
+                    format!("#{}", max_value)
+                }
             },
             bits,
             if sign_extend {
@@ -0,0 +1,304 @@
+//! Interactive terminal debugger in the style of lc3sim.
+//!
+//! Commands and program input are both read from stdin, so a program waiting for a key consumes
+//! the next character typed at the prompt.
+
+use std::io::{self, Write};
+
+use colored::Colorize;
+
+use crate::console::{Console, StdConsole};
+use crate::debugger::{Access, Debugger, Resume, StopReason, Watchpoint};
+use crate::disassembler::disassemble;
+
+const HELP: &str = "\
+break LOCATION        stop when execution reaches a label or address
+break LOCATION if C   stop there only when condition C holds, e.g. `R5 != 0`
+break if C            stop wherever condition C becomes true, e.g. `R6 < x2F00`
+delete LOCATION       remove a breakpoint
+delete all            remove every breakpoint and condition
+watch START [END]     stop when an address in the range is written
+rwatch START [END]    stop when an address in the range is read
+awatch START [END]    stop when an address in the range is read or written
+unwatch LOCATION      remove the watchpoints covering an address
+info                  list breakpoints, conditions and watchpoints
+step                  execute one instruction, entering subroutines
+next                  execute one instruction, running subroutines to completion
+finish                run until the current subroutine returns
+continue              run until a breakpoint, watchpoint or HALT
+back                  undo one instruction
+rcontinue             run backward until a breakpoint or watchpoint
+last-write LOCATION   run backward to just before the last write to an address
+regs                  print the registers and condition codes
+mem ADDRESS [COUNT]   print COUNT words of memory starting at ADDRESS
+set REGISTER VALUE    set R0-R7, PC or PSR
+set ADDRESS VALUE     set a word of memory
+list                  print the source line of the current instruction
+help                  print this message
+quit                  exit the debugger";
+
+/// Runs the debugger on the source until the user quits or stdin is closed.
+pub fn run(source: &str, os: bool) {
+    let mut debugger = match Debugger::load(source, os) {
+        Ok(debugger) => debugger,
+        Err(errors) => {
+            for error in errors {
+                println!("{}: {}", "error".red().bold(), error.bold());
+            }
+            return;
+        }
+    };
+
+    let lines = source.split('\n').collect::<Vec<&str>>();
+    let mut console = StdConsole::new();
+
+    print_location(&debugger, &lines);
+    loop {
+        print!("(lc3) ");
+        let _ = io::stdout().flush();
+        let Some(line) = read_line(&mut console) else {
+            println!();
+            return;
+        };
+
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        let Some(&command) = words.first() else {
+            continue;
+        };
+        if command == "quit" || command == "q" {
+            return;
+        }
+
+        if let Err(message) = execute(&mut debugger, &mut console, &lines, command, &words[1..]) {
+            println!("{}: {}", "error".red().bold(), message.bold());
+        }
+    }
+}
+
+fn read_line(console: &mut dyn Console) -> Option<String> {
+    let mut line = Vec::new();
+    loop {
+        match console.read_char()? {
+            b'\n' => return Some(String::from_utf8_lossy(&line).trim().to_owned()),
+            c => line.push(c),
+        }
+    }
+}
+
+fn execute(
+    debugger: &mut Debugger,
+    console: &mut dyn Console,
+    lines: &[&str],
+    command: &str,
+    args: &[&str],
+) -> Result<(), String> {
+    let location = |debugger: &Debugger| -> Result<u16, String> {
+        let text = args.first().ok_or("Expected a label or address")?;
+        debugger
+            .parse_word(text)
+            .ok_or(format!("Unknown label or address `{}`", text))
+    };
+
+    match command {
+        "break" | "b" => {
+            let condition = match args.iter().position(|&arg| arg == "if") {
+                Some(index) => Some(debugger.parse_condition(&args[index + 1..].join(" "))?),
+                None => None,
+            };
+
+            match (args.first(), condition) {
+                (Some(&"if"), Some(condition)) => {
+                    println!("Breaking when `{}`", condition.text);
+                    debugger.conditions.push(condition);
+                }
+                (_, condition) => {
+                    let address = location(debugger)?;
+                    println!("Breakpoint at x{:04X}", address);
+                    debugger.breakpoints.insert(address, condition);
+                }
+            }
+        }
+        "delete" | "d" => {
+            if args.first() == Some(&"all") {
+                debugger.breakpoints.clear();
+                debugger.conditions.clear();
+                return Ok(());
+            }
+
+            let address = location(debugger)?;
+            if debugger.breakpoints.remove(&address).is_none() {
+                return Err(format!("No breakpoint at x{:04X}", address));
+            }
+        }
+        "watch" | "rwatch" | "awatch" => {
+            let start = location(debugger)?;
+            let end = match args.get(1) {
+                Some(text) => debugger
+                    .parse_word(text)
+                    .ok_or(format!("Unknown label or address `{}`", text))?,
+                None => start,
+            };
+            if end < start {
+                return Err(format!("x{:04X} comes before x{:04X}", end, start));
+            }
+
+            debugger.watchpoints.push(Watchpoint {
+                start,
+                end,
+                read: command != "watch",
+                write: command != "rwatch",
+            });
+            println!("Watching x{:04X}-x{:04X}", start, end);
+        }
+        "unwatch" => {
+            if args.first() == Some(&"all") {
+                debugger.watchpoints.clear();
+                return Ok(());
+            }
+
+            let address = location(debugger)?;
+            let count = debugger.watchpoints.len();
+            debugger
+                .watchpoints
+                .retain(|watchpoint| !(watchpoint.start..=watchpoint.end).contains(&address));
+            if debugger.watchpoints.len() == count {
+                return Err(format!("Not watching x{:04X}", address));
+            }
+        }
+        "info" | "i" => print_info(debugger),
+        "step" | "s" => resume(debugger, console, lines, Resume::Step),
+        "next" | "n" => resume(debugger, console, lines, Resume::Next),
+        "finish" => resume(debugger, console, lines, Resume::StepOut),
+        "continue" | "c" => resume(debugger, console, lines, Resume::Continue),
+        "back" => resume(debugger, console, lines, Resume::StepBack),
+        "rcontinue" => resume(debugger, console, lines, Resume::ReverseContinue),
+        "last-write" => {
+            let address = location(debugger)?;
+            resume(debugger, console, lines, Resume::ReverseToWrite(address));
+        }
+        "regs" | "r" => print_registers(debugger),
+        "mem" | "m" => {
+            let start = location(debugger)?;
+            let count = match args.get(1) {
+                Some(text) => debugger
+                    .parse_word(text)
+                    .ok_or(format!("Invalid count `{}`", text))?,
+                None => 1,
+            };
+            for offset in 0..count {
+                let address = start.wrapping_add(offset);
+                let value = debugger.machine.read_memory(address);
+                println!("x{:04X}: x{:04X} (#{})", address, value, value as i16);
+            }
+        }
+        "set" => {
+            let [target, value] = args else {
+                return Err("Expected `set REGISTER VALUE` or `set ADDRESS VALUE`".to_owned());
+            };
+            let value = debugger
+                .parse_word(value)
+                .ok_or(format!("Invalid value `{}`", value))?;
+
+            let machine = &mut debugger.machine;
+            match target.to_uppercase().as_str() {
+                "PC" => machine.pc = value,
+                "PSR" => machine.psr = value,
+                register @ ("R0" | "R1" | "R2" | "R3" | "R4" | "R5" | "R6" | "R7") => {
+                    machine.registers[(register.as_bytes()[1] - b'0') as usize] = value;
+                }
+                _ => {
+                    let address = debugger
+                        .parse_word(target)
+                        .ok_or(format!("Unknown register or address `{}`", target))?;
+                    debugger.machine.write_memory(address, value);
+                }
+            }
+            debugger.clear_history();
+        }
+        "list" | "l" => print_location(debugger, lines),
+        "help" | "h" => println!("{}", HELP),
+        _ => return Err(format!("Unknown command `{}`, try `help`", command)),
+    }
+
+    Ok(())
+}
+
+fn resume(debugger: &mut Debugger, console: &mut dyn Console, lines: &[&str], mode: Resume) {
+    match debugger.resume(mode, console, &mut || false) {
+        StopReason::Step | StopReason::Paused => {}
+        StopReason::Breakpoint(address) => println!("Breakpoint at x{:04X}", address),
+        StopReason::Condition(index) => println!("`{}` is true", debugger.conditions[index].text),
+        StopReason::Watchpoint(address, access) if mode.is_reverse() => {
+            let verb = match access {
+                Access::Read => "read",
+                Access::Write => "written",
+            };
+            println!("x{:04X} is {} by the next instruction", address, verb);
+        }
+        StopReason::Watchpoint(address, Access::Read) => println!("x{:04X} was read", address),
+        StopReason::Watchpoint(address, Access::Write) => {
+            let value = debugger.machine.read_memory(address);
+            println!("x{:04X} was written with x{:04X}", address, value);
+        }
+        StopReason::StartOfHistory => println!("Reached the start of the recorded history"),
+        StopReason::Halted => {
+            println!("Program halted");
+            return;
+        }
+        StopReason::Error(error) => println!("{}: {}", "error".red().bold(), error),
+    }
+
+    print_location(debugger, lines);
+}
+
+/// Prints the address and source line of the current instruction.
+fn print_location(debugger: &Debugger, lines: &[&str]) {
+    let pc = debugger.machine.pc;
+    match debugger.line_at(pc) {
+        Some(line) => println!("x{:04X} {:>4}: {}", pc, line, lines[line - 1].trim_end()),
+        None => println!(
+            "x{:04X}       {}",
+            pc,
+            disassemble(debugger.machine.read_memory(pc), pc)
+        ),
+    }
+}
+
+fn print_info(debugger: &Debugger) {
+    for (address, condition) in &debugger.breakpoints {
+        match condition {
+            Some(condition) => println!("break x{:04X} if {}", address, condition.text),
+            None => println!("break x{:04X}", address),
+        }
+    }
+    for condition in &debugger.conditions {
+        println!("break if {}", condition.text);
+    }
+    for watchpoint in &debugger.watchpoints {
+        let command = match (watchpoint.read, watchpoint.write) {
+            (true, true) => "awatch",
+            (true, false) => "rwatch",
+            _ => "watch",
+        };
+        println!(
+            "{} x{:04X} x{:04X}",
+            command, watchpoint.start, watchpoint.end
+        );
+    }
+}
+
+fn print_registers(debugger: &Debugger) {
+    let machine = &debugger.machine;
+    for (i, value) in machine.registers.iter().enumerate() {
+        print!("R{} x{:04X}  ", i, value);
+        if i % 4 == 3 {
+            println!();
+        }
+    }
+    println!(
+        "PC x{:04X}  PSR x{:04X}  CC {}",
+        machine.pc,
+        machine.psr,
+        machine.condition_codes()
+    );
+}
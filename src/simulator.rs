@@ -0,0 +1,830 @@
+//! LC3 simulator that executes assembled programs.
+//!
+//! Instruction semantics follow the third edition of the ISA: `TRAP`, interrupts and exceptions
+//! push the PSR and PC onto the supervisor stack, switching to it from user mode, and handlers
+//! return with `RTI`. As in the second edition, `LEA` sets the condition codes and `TRAP` also
+//! saves the return address in R7, so programs written for either edition behave the same.
+
+use std::fmt;
+
+use crate::assembler::Program;
+use crate::console::Console;
+use crate::os;
+
+pub const MEMORY_SIZE: usize = 0x10000;
+
+/// Privilege bit of the PSR, set while running in user mode.
+pub const PSR_USER: u16 = 0x8000;
+/// Priority level bits of the PSR.
+pub const PSR_PRIORITY: u16 = 0x0700;
+pub const PSR_N: u16 = 0x4;
+pub const PSR_Z: u16 = 0x2;
+pub const PSR_P: u16 = 0x1;
+
+/// Initial supervisor stack pointer, the stack grows down from just below user programs.
+pub const SUPERVISOR_STACK: u16 = 0x3000;
+
+/// Start of the interrupt vector table, which holds the addresses of the interrupt and exception
+/// handlers.
+pub const INTERRUPT_VECTOR_TABLE: u16 = 0x0100;
+pub const PRIVILEGE_VIOLATION_VECTOR: u8 = 0x00;
+pub const ILLEGAL_OPCODE_VECTOR: u8 = 0x01;
+pub const ACCESS_VIOLATION_VECTOR: u8 = 0x02;
+pub const KEYBOARD_VECTOR: u8 = 0x80;
+pub const KEYBOARD_PRIORITY: u16 = 4;
+
+/// Start of the memory-mapped device registers, which user mode cannot access.
+pub const DEVICE_REGISTERS: u16 = 0xFE00;
+/// Keyboard status register, bit 15 is set while a character is ready and bit 14 enables
+/// keyboard interrupts.
+pub const KBSR: u16 = 0xFE00;
+/// Keyboard data register, reading it consumes the ready character.
+pub const KBDR: u16 = 0xFE02;
+/// Display status register, bit 15 is set when the display is ready.
+pub const DSR: u16 = 0xFE04;
+/// Display data register, writing it outputs a character.
+pub const DDR: u16 = 0xFE06;
+/// Machine control register, clearing bit 15 stops the clock.
+pub const MCR: u16 = 0xFFFE;
+
+#[derive(Clone)]
+pub struct Machine {
+    pub memory: Vec<u16>,
+    pub registers: [u16; 8],
+    pub pc: u16,
+    pub psr: u16,
+    pub halted: bool,
+    /// Supervisor stack pointer, swapped into R6 while handling an interrupt from user mode.
+    pub saved_ssp: u16,
+    /// User stack pointer, swapped back into R6 when `RTI` returns to user mode.
+    pub saved_usp: u16,
+    /// Handle the standard trap routines natively instead of jumping through the trap vector
+    /// table, which is empty unless an operating system has been loaded.
+    pub emulate_traps: bool,
+    /// Character latched into KBDR that the program has not read yet.
+    keyboard: Option<u8>,
+    /// Addresses read by the last step, not counting the instruction fetch, e.g. for
+    /// watchpoints.
+    pub reads: Vec<u16>,
+    /// Memory written by the last step.
+    pub writes: Vec<MemoryWrite>,
+    /// Vector of the interrupt or exception whose handler the last step jumped to, instead of
+    /// executing an instruction or after one faulted.
+    pub interrupt: Option<u8>,
+}
+
+/// Word written by a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: u16,
+    /// Word the address held before, for undoing the write.
+    pub before: u16,
+    /// Word that was written, which device registers such as DDR do not hold on to.
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulatorError {
+    /// The reserved opcode 1101 was executed at the given address.
+    IllegalOpcode(u16),
+    /// `RTI` was executed in user mode at the given address.
+    PrivilegeViolation(u16),
+    /// The instruction at the given address accessed the device registers in user mode.
+    AccessViolation(u16),
+    /// An interrupt with the given vector was raised at the given address, but its vector table
+    /// entry is empty.
+    UnhandledInterrupt(u8, u16),
+    /// The instruction at the given address waited for console input after it ran out.
+    EndOfInput(u16),
+}
+
+impl fmt::Display for SimulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimulatorError::IllegalOpcode(address) => {
+                write!(f, "Illegal opcode at x{:04X}", address)
+            }
+            SimulatorError::PrivilegeViolation(address) => {
+                write!(f, "Privilege mode violation at x{:04X}", address)
+            }
+            SimulatorError::AccessViolation(address) => {
+                write!(f, "Access control violation at x{:04X}", address)
+            }
+            SimulatorError::UnhandledInterrupt(vector, address) => {
+                write!(
+                    f,
+                    "Interrupt x{:02X} at x{:04X} has no handler",
+                    vector, address
+                )
+            }
+            SimulatorError::EndOfInput(address) => {
+                write!(f, "Ran out of console input at x{:04X}", address)
+            }
+        }
+    }
+}
+
+impl SimulatorError {
+    /// Exception vector that handles the error, if it is an exception.
+    pub fn exception_vector(&self) -> Option<u8> {
+        match self {
+            SimulatorError::PrivilegeViolation(_) => Some(PRIVILEGE_VIOLATION_VECTOR),
+            SimulatorError::IllegalOpcode(_) => Some(ILLEGAL_OPCODE_VECTOR),
+            SimulatorError::AccessViolation(_) => Some(ACCESS_VIOLATION_VECTOR),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    /// Creates a machine in supervisor mode at priority 0 with cleared memory, the Z flag set
+    /// and R6 pointing at the supervisor stack, which grows down from x3000.
+    pub fn new() -> Self {
+        let mut memory = vec![0; MEMORY_SIZE];
+        memory[MCR as usize] = 0x8000;
+        let mut registers = [0; 8];
+        registers[6] = SUPERVISOR_STACK;
+
+        Machine {
+            memory,
+            registers,
+            pc: 0x3000,
+            psr: PSR_Z,
+            halted: false,
+            saved_ssp: SUPERVISOR_STACK,
+            saved_usp: 0,
+            emulate_traps: true,
+            keyboard: None,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            interrupt: None,
+        }
+    }
+
+    /// Copies every segment of the program into memory and starts execution at the first one.
+    pub fn load(&mut self, program: &Program) {
+        self.copy_segments(program);
+
+        if let Some(segment) = program.segments.first() {
+            self.pc = segment.origin;
+        }
+    }
+
+    /// Loads the bundled operating system and runs traps through it instead of emulating them.
+    /// In supervisor mode R6 is reset to the supervisor stack the trap handlers run on.
+    pub fn load_os(&mut self) {
+        self.copy_segments(&os::assemble());
+        self.emulate_traps = false;
+
+        if !self.is_user_mode() {
+            self.registers[6] = self.saved_ssp;
+        }
+    }
+
+    fn copy_segments(&mut self, program: &Program) {
+        for segment in &program.segments {
+            let start = segment.origin as usize;
+            self.memory[start..start + segment.words.len()].copy_from_slice(&segment.words);
+        }
+    }
+
+    /// Switches to user mode, the way an operating system starts a program with `RTI`. R6 is
+    /// saved as the supervisor stack pointer for traps and interrupts, and the user stack pointer
+    /// is swapped in.
+    pub fn enter_user_mode(&mut self) {
+        if !self.is_user_mode() {
+            self.saved_ssp = self.registers[6];
+            self.registers[6] = self.saved_usp;
+        }
+        self.psr |= PSR_USER;
+    }
+
+    pub fn is_user_mode(&self) -> bool {
+        self.psr & PSR_USER != 0
+    }
+
+    /// Priority level the machine is running at, from 0 to 7.
+    pub fn priority(&self) -> u16 {
+        (self.psr & PSR_PRIORITY) >> 8
+    }
+
+    /// Condition codes formatted as e.g. `"z"` or `"n"`.
+    pub fn condition_codes(&self) -> String {
+        [(PSR_N, 'n'), (PSR_Z, 'z'), (PSR_P, 'p')]
+            .iter()
+            .filter(|(flag, _)| self.psr & flag != 0)
+            .map(|(_, c)| *c)
+            .collect()
+    }
+
+    /// Reads memory without side effects, e.g. for inspecting it from a debugger.
+    pub fn read_memory(&self, address: u16) -> u16 {
+        match address {
+            KBSR => (self.keyboard.is_some() as u16) << 15 | (self.memory[KBSR as usize] & 0x4000),
+            KBDR => self.keyboard.unwrap_or(0) as u16,
+            DSR => 0x8000,
+            _ => self.memory[address as usize],
+        }
+    }
+
+    /// Writes memory without side effects, e.g. for editing it from a debugger.
+    pub fn write_memory(&mut self, address: u16, value: u16) {
+        self.memory[address as usize] = value;
+    }
+
+    /// Reads memory as the running program sees it. Polling KBSR waits for the next character.
+    fn read_word(
+        &mut self,
+        address: u16,
+        console: &mut dyn Console,
+    ) -> Result<u16, SimulatorError> {
+        self.check_access(address)?;
+        self.reads.push(address);
+
+        match address {
+            KBSR if self.keyboard.is_none() => {
+                self.keyboard = Some(
+                    console
+                        .read_char()
+                        .ok_or(SimulatorError::EndOfInput(self.pc.wrapping_sub(1)))?,
+                );
+            }
+            KBDR => return Ok(self.keyboard.take().unwrap_or(0) as u16),
+            _ => {}
+        }
+
+        Ok(self.read_memory(address))
+    }
+
+    /// Writes memory as the running program sees it, driving the display and machine control.
+    fn write_word(
+        &mut self,
+        address: u16,
+        value: u16,
+        console: &mut dyn Console,
+    ) -> Result<(), SimulatorError> {
+        self.check_access(address)?;
+        self.store(address, value, console);
+
+        Ok(())
+    }
+
+    /// Writes memory with the side effects of the device registers, without checking access.
+    fn store(&mut self, address: u16, value: u16, console: &mut dyn Console) {
+        self.writes.push(MemoryWrite {
+            address,
+            before: self.memory[address as usize],
+            value,
+        });
+
+        match address {
+            KBSR => self.memory[KBSR as usize] = value & 0x4000,
+            DDR => console.write_char(value as u8),
+            MCR => {
+                self.memory[MCR as usize] = value;
+                if value & 0x8000 == 0 {
+                    self.halted = true;
+                }
+            }
+            _ => self.memory[address as usize] = value,
+        }
+    }
+
+    fn check_access(&self, address: u16) -> Result<(), SimulatorError> {
+        if self.is_user_mode() && address >= DEVICE_REGISTERS {
+            return Err(SimulatorError::AccessViolation(self.pc.wrapping_sub(1)));
+        }
+
+        Ok(())
+    }
+
+    /// Runs until the machine halts or `max_steps` instructions have executed, returning the
+    /// number of instructions executed.
+    pub fn run(
+        &mut self,
+        console: &mut dyn Console,
+        max_steps: u64,
+    ) -> Result<u64, SimulatorError> {
+        let mut steps = 0;
+        while !self.halted && steps < max_steps {
+            self.step(console)?;
+            steps += 1;
+        }
+
+        Ok(steps)
+    }
+
+    /// Takes a pending keyboard interrupt, or fetches, decodes and executes a single
+    /// instruction. Exceptions jump to their handler when the vector table has one. Otherwise
+    /// the PC is left on the instruction so it can be retried, e.g. after more input is
+    /// available.
+    pub fn step(&mut self, console: &mut dyn Console) -> Result<(), SimulatorError> {
+        let address = self.pc;
+        self.reads.clear();
+        self.writes.clear();
+        self.interrupt = None;
+
+        if self.keyboard_interrupt_pending(console) {
+            if !self.interrupt(KEYBOARD_VECTOR, Some(KEYBOARD_PRIORITY), console) {
+                return Err(SimulatorError::UnhandledInterrupt(KEYBOARD_VECTOR, address));
+            }
+            return Ok(());
+        }
+
+        self.execute(address, console).or_else(|error| {
+            self.pc = address.wrapping_add(1);
+            match error.exception_vector() {
+                Some(vector) if self.interrupt(vector, None, console) => Ok(()),
+                _ => {
+                    self.pc = address;
+                    Err(error)
+                }
+            }
+        })
+    }
+
+    fn keyboard_interrupt_pending(&mut self, console: &mut dyn Console) -> bool {
+        if self.memory[KBSR as usize] & 0x4000 == 0 || self.priority() >= KEYBOARD_PRIORITY {
+            return false;
+        }
+
+        if self.keyboard.is_none() {
+            self.keyboard = console.poll_char();
+        }
+        self.keyboard.is_some()
+    }
+
+    /// Saves the PSR and PC on the supervisor stack and jumps to the handler for the vector,
+    /// raising the priority if one is given. Returns `false` if the vector has no handler.
+    fn interrupt(&mut self, vector: u8, priority: Option<u16>, console: &mut dyn Console) -> bool {
+        let handler = self.memory[INTERRUPT_VECTOR_TABLE as usize + vector as usize];
+        if handler == 0 {
+            return false;
+        }
+
+        self.enter_supervisor_mode(handler, console);
+        if let Some(priority) = priority {
+            self.psr = (self.psr & !PSR_PRIORITY) | priority << 8;
+        }
+        self.interrupt = Some(vector);
+
+        true
+    }
+
+    /// Saves the PSR and PC on the supervisor stack, switching to it from user mode, and jumps to
+    /// the handler in supervisor mode.
+    fn enter_supervisor_mode(&mut self, handler: u16, console: &mut dyn Console) {
+        let psr = self.psr;
+        if self.is_user_mode() {
+            self.saved_usp = self.registers[6];
+            self.registers[6] = self.saved_ssp;
+        }
+        self.psr &= !PSR_USER;
+        self.push(psr, console);
+        self.push(self.pc, console);
+
+        self.pc = handler;
+    }
+
+    fn push(&mut self, value: u16, console: &mut dyn Console) {
+        self.registers[6] = self.registers[6].wrapping_sub(1);
+        self.store(self.registers[6], value, console);
+    }
+
+    fn execute(&mut self, address: u16, console: &mut dyn Console) -> Result<(), SimulatorError> {
+        self.pc = address.wrapping_add(1);
+        self.check_access(address)?;
+        let instruction = self.read_memory(address);
+
+        let dr = ((instruction >> 9) & 0x7) as usize;
+        let sr1 = ((instruction >> 6) & 0x7) as usize;
+        let pc_offset9 = sign_extend(instruction, 9);
+
+        match instruction >> 12 {
+            // ADD, AND
+            0b0001 | 0b0101 => {
+                let operand = if instruction & 0x20 != 0 {
+                    sign_extend(instruction, 5)
+                } else {
+                    self.registers[(instruction & 0x7) as usize]
+                };
+
+                self.registers[dr] = if instruction >> 12 == 0b0001 {
+                    self.registers[sr1].wrapping_add(operand)
+                } else {
+                    self.registers[sr1] & operand
+                };
+                self.set_condition_codes(self.registers[dr]);
+            }
+            // BR
+            0b0000 => {
+                if (instruction >> 9) & self.psr & 0x7 != 0 {
+                    self.pc = self.pc.wrapping_add(pc_offset9);
+                }
+            }
+            // JMP, RET
+            0b1100 => {
+                self.pc = self.registers[sr1];
+            }
+            // JSR, JSRR
+            0b0100 => {
+                let return_address = self.pc;
+                self.pc = if instruction & 0x800 != 0 {
+                    self.pc.wrapping_add(sign_extend(instruction, 11))
+                } else {
+                    self.registers[sr1]
+                };
+                self.registers[7] = return_address;
+            }
+            // LD
+            0b0010 => {
+                self.registers[dr] = self.read_word(self.pc.wrapping_add(pc_offset9), console)?;
+                self.set_condition_codes(self.registers[dr]);
+            }
+            // LDI
+            0b1010 => {
+                let pointer = self.read_word(self.pc.wrapping_add(pc_offset9), console)?;
+                self.registers[dr] = self.read_word(pointer, console)?;
+                self.set_condition_codes(self.registers[dr]);
+            }
+            // LDR
+            0b0110 => {
+                let address = self.registers[sr1].wrapping_add(sign_extend(instruction, 6));
+                self.registers[dr] = self.read_word(address, console)?;
+                self.set_condition_codes(self.registers[dr]);
+            }
+            // LEA
+            0b1110 => {
+                self.registers[dr] = self.pc.wrapping_add(pc_offset9);
+                self.set_condition_codes(self.registers[dr]);
+            }
+            // NOT
+            0b1001 => {
+                self.registers[dr] = !self.registers[sr1];
+                self.set_condition_codes(self.registers[dr]);
+            }
+            // ST
+            0b0011 => {
+                self.write_word(
+                    self.pc.wrapping_add(pc_offset9),
+                    self.registers[dr],
+                    console,
+                )?;
+            }
+            // STI
+            0b1011 => {
+                let pointer = self.read_word(self.pc.wrapping_add(pc_offset9), console)?;
+                self.write_word(pointer, self.registers[dr], console)?;
+            }
+            // STR
+            0b0111 => {
+                let address = self.registers[sr1].wrapping_add(sign_extend(instruction, 6));
+                self.write_word(address, self.registers[dr], console)?;
+            }
+            // TRAP
+            0b1111 => {
+                let trapvect8 = instruction & 0xFF;
+                let emulated =
+                    self.emulate_traps && self.emulate_trap(trapvect8, address, console)?;
+
+                self.registers[7] = self.pc;
+                if !emulated {
+                    let handler = self.read_word(trapvect8, console)?;
+                    self.enter_supervisor_mode(handler, console);
+                }
+            }
+            // RTI
+            0b1000 => {
+                if self.is_user_mode() {
+                    return Err(SimulatorError::PrivilegeViolation(address));
+                }
+
+                self.pc = self.read_word(self.registers[6], console)?;
+                self.psr = self.read_word(self.registers[6].wrapping_add(1), console)?;
+                self.registers[6] = self.registers[6].wrapping_add(2);
+
+                if self.is_user_mode() {
+                    self.saved_ssp = self.registers[6];
+                    self.registers[6] = self.saved_usp;
+                }
+            }
+            // Reserved
+            _ => return Err(SimulatorError::IllegalOpcode(address)),
+        }
+
+        Ok(())
+    }
+
+    /// Performs one of the standard trap routines, returning `false` for any other vector.
+    fn emulate_trap(
+        &mut self,
+        trapvect8: u16,
+        address: u16,
+        console: &mut dyn Console,
+    ) -> Result<bool, SimulatorError> {
+        match trapvect8 {
+            // GETC
+            0x20 => {
+                let c = self
+                    .keyboard
+                    .take()
+                    .or_else(|| console.read_char())
+                    .ok_or(SimulatorError::EndOfInput(address))?;
+                self.registers[0] = c as u16;
+            }
+            // OUT
+            0x21 => console.write_char(self.registers[0] as u8),
+            // PUTS
+            0x22 => {
+                let mut address = self.registers[0];
+                loop {
+                    let c = self.read_memory(address);
+                    if c == 0 {
+                        break;
+                    }
+                    console.write_char(c as u8);
+                    address = address.wrapping_add(1);
+                }
+            }
+            // IN
+            0x23 => {
+                for c in "\nInput a character> ".bytes() {
+                    console.write_char(c);
+                }
+
+                let c = self
+                    .keyboard
+                    .take()
+                    .or_else(|| console.read_char())
+                    .ok_or(SimulatorError::EndOfInput(address))?;
+                console.write_char(c);
+                self.registers[0] = c as u16;
+            }
+            // PUTSP
+            0x24 => {
+                let mut address = self.registers[0];
+                'outer: loop {
+                    let word = self.read_memory(address);
+                    for c in [word & 0xFF, word >> 8] {
+                        if c == 0 {
+                            break 'outer;
+                        }
+                        console.write_char(c as u8);
+                    }
+                    address = address.wrapping_add(1);
+                }
+            }
+            // HALT
+            0x25 => self.halted = true,
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    fn set_condition_codes(&mut self, value: u16) {
+        let flag = match value as i16 {
+            v if v < 0 => PSR_N,
+            0 => PSR_Z,
+            _ => PSR_P,
+        };
+
+        self.psr = (self.psr & !0x7) | flag;
+    }
+}
+
+/// Sign extends the lowest `bits` bits of `value` to 16 bits.
+pub fn sign_extend(value: u16, bits: u32) -> u16 {
+    let shift = 16 - bits;
+    (((value << shift) as i16) >> shift) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::console::BufferConsole;
+
+    fn machine(source: &str) -> Machine {
+        let (program, _) = assembler::assemble_source(source).unwrap();
+        let mut machine = Machine::new();
+        machine.load(&program);
+        machine
+    }
+
+    fn run(machine: &mut Machine, steps: u64) -> BufferConsole {
+        let mut console = BufferConsole::new("");
+        machine.run(&mut console, steps).unwrap();
+        console
+    }
+
+    #[test]
+    fn sign_extends() {
+        assert_eq!(sign_extend(0x1F, 5), 0xFFFF);
+        assert_eq!(sign_extend(0x0F, 5), 0x000F);
+        assert_eq!(sign_extend(0x1FF, 9), 0xFFFF);
+        assert_eq!(sign_extend(0x100, 9), 0xFF00);
+        // Bits above the field are ignored
+        assert_eq!(sign_extend(0x1234, 6), 0xFFF4);
+    }
+
+    #[test]
+    fn operate_instructions_set_condition_codes() {
+        let mut machine = machine(
+            ".ORIG x3000\nAND R0, R0, #0\nADD R1, R0, #-3\nNOT R2, R1\nADD R3, R1, R2\nAND R4, R2, #1\n.END",
+        );
+
+        run(&mut machine, 1);
+        assert_eq!(machine.condition_codes(), "z");
+        run(&mut machine, 1);
+        assert_eq!(
+            (machine.registers[1], machine.condition_codes().as_str()),
+            (0xFFFD, "n")
+        );
+        run(&mut machine, 1);
+        assert_eq!(
+            (machine.registers[2], machine.condition_codes().as_str()),
+            (2, "p")
+        );
+        run(&mut machine, 1);
+        assert_eq!(
+            (machine.registers[3], machine.condition_codes().as_str()),
+            (0xFFFF, "n")
+        );
+        run(&mut machine, 1);
+        assert_eq!(
+            (machine.registers[4], machine.condition_codes().as_str()),
+            (0, "z")
+        );
+    }
+
+    #[test]
+    fn memory_instructions_use_sign_extended_offsets() {
+        let mut machine = machine(
+            "
+            .ORIG x3000
+            LEA R0, DATA
+            LDR R1, R0, #1
+            LDI R2, POINTER
+            STR R1, R0, #-1
+            ST R2, DATA
+            STI R1, POINTER
+            HALT
+            SLOT .BLKW #1
+            DATA .FILL #0
+            .FILL x8000
+            POINTER .FILL DATA
+            .END
+            ",
+        );
+        run(&mut machine, 100);
+
+        assert_eq!(machine.registers[1], 0x8000);
+        assert_eq!(machine.registers[2], 0);
+        assert_eq!(machine.memory[0x3007], 0x8000);
+        assert_eq!(machine.memory[0x3008], 0x8000);
+    }
+
+    #[test]
+    fn branches_and_subroutines() {
+        let mut machine = machine(
+            "
+            .ORIG x3000
+            AND R0, R0, #0
+            ADD R1, R0, #3
+            LOOP ADD R0, R0, #2
+            ADD R1, R1, #-1
+            BRp LOOP
+            JSR DOUBLE
+            LEA R2, DOUBLE
+            JSRR R2
+            HALT
+            DOUBLE ADD R0, R0, R0
+            RET
+            .END
+            ",
+        );
+        run(&mut machine, 100);
+
+        assert!(machine.halted);
+        assert_eq!(machine.registers[0], 24);
+        // `HALT` saves its return address last
+        assert_eq!(machine.registers[7], 0x3009);
+    }
+
+    #[test]
+    fn emulated_traps_perform_io() {
+        let mut machine =
+            machine(".ORIG x3000\nGETC\nOUT\nLEA R0, TEXT\nPUTS\nHALT\nTEXT .STRINGZ \"ok\"\n.END");
+        let mut console = BufferConsole::new("a");
+        machine.run(&mut console, 100).unwrap();
+
+        assert!(machine.halted);
+        assert_eq!(console.output_string(), "aok");
+        assert_eq!(machine.registers[7], 0x3005);
+    }
+
+    #[test]
+    fn traps_switch_to_the_supervisor_stack_and_return_with_rti() {
+        let mut machine = machine(".ORIG x3000\nTRAP x30\nHALT\n.END");
+        let (handler, _) =
+            assembler::assemble_source(".ORIG x4000\nADD R1, R1, #1\nRTI\n.END").unwrap();
+        machine.copy_segments(&handler);
+        machine.memory[0x30] = 0x4000;
+        machine.enter_user_mode();
+        machine.registers[6] = 0xF000;
+        let user_psr = machine.psr;
+
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x4000);
+        assert!(!machine.is_user_mode());
+        assert_eq!(machine.registers[6], 0x2FFE);
+        assert_eq!(machine.memory[0x2FFE], 0x3001);
+        assert_eq!(machine.memory[0x2FFF], user_psr);
+        assert_eq!(machine.saved_usp, 0xF000);
+
+        run(&mut machine, 2);
+        assert_eq!(machine.pc, 0x3001);
+        assert!(machine.is_user_mode());
+        assert_eq!(machine.registers[6], 0xF000);
+        assert_eq!(machine.saved_ssp, 0x3000);
+        assert_eq!(machine.registers[1], 1);
+    }
+
+    #[test]
+    fn bundled_os_runs_traps_on_the_supervisor_stack() {
+        let mut machine = machine(".ORIG x3000\nLD R0, CHAR\nOUT\nHALT\nCHAR .FILL x41\n.END");
+        machine.load_os();
+
+        run(&mut machine, 2);
+        assert_eq!(machine.registers[6], SUPERVISOR_STACK - 2);
+        assert_eq!(machine.memory[SUPERVISOR_STACK as usize - 2], 0x3002);
+        assert_eq!(machine.memory[MCR as usize], 0x8000);
+        assert_eq!(machine.memory[0xFFFF], 0);
+
+        let console = run(&mut machine, 10_000);
+        assert!(machine.halted);
+        assert!(console.output_string().starts_with('A'));
+        assert_eq!(machine.memory[0xFFFF], 0);
+    }
+
+    #[test]
+    fn bundled_os_runs_traps_from_user_mode() {
+        let mut machine =
+            machine(".ORIG x3000\nLEA R0, TEXT\nPUTS\nHALT\nTEXT .STRINGZ \"hi\"\n.END");
+        machine.load_os();
+        machine.enter_user_mode();
+        let console = run(&mut machine, 10_000);
+
+        assert!(machine.halted);
+        assert!(console.output_string().starts_with("hi"));
+    }
+
+    #[test]
+    fn keyboard_interrupts_raise_the_priority() {
+        let mut machine = machine(".ORIG x3000\nBRnzp #-1\n.END");
+        machine.memory[INTERRUPT_VECTOR_TABLE as usize + KEYBOARD_VECTOR as usize] = 0x5000;
+        machine.memory[KBSR as usize] = 0x4000;
+        machine.enter_user_mode();
+
+        let mut console = BufferConsole::new("k");
+        machine.step(&mut console).unwrap();
+
+        assert_eq!(machine.pc, 0x5000);
+        assert_eq!(machine.priority(), KEYBOARD_PRIORITY);
+        assert!(!machine.is_user_mode());
+        assert_eq!(machine.memory[0x2FFF] & PSR_USER, PSR_USER);
+        assert_eq!(machine.read_memory(KBDR), b'k' as u16);
+    }
+
+    #[test]
+    fn user_mode_exceptions() {
+        let mut user = machine(".ORIG x3000\nRTI\n.END");
+        user.enter_user_mode();
+        assert_eq!(
+            user.step(&mut BufferConsole::new("")),
+            Err(SimulatorError::PrivilegeViolation(0x3000))
+        );
+
+        let mut user = machine(".ORIG x3000\nLDI R0, DSR_ADDRESS\nDSR_ADDRESS .FILL xFE04\n.END");
+        user.enter_user_mode();
+        assert_eq!(
+            user.step(&mut BufferConsole::new("")),
+            Err(SimulatorError::AccessViolation(0x3000))
+        );
+        // The PC stays on the faulting instruction when there is no handler
+        assert_eq!(user.pc, 0x3000);
+
+        let mut illegal = machine(".ORIG x3000\n.FILL xD000\n.END");
+        illegal.memory[INTERRUPT_VECTOR_TABLE as usize + ILLEGAL_OPCODE_VECTOR as usize] = 0x5000;
+        illegal.step(&mut BufferConsole::new("")).unwrap();
+        assert_eq!(illegal.pc, 0x5000);
+        assert_eq!(illegal.memory[0x2FFE], 0x3001);
+    }
+}
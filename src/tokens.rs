@@ -1,3 +1,6 @@
+use std::fmt;
+use std::num::ParseIntError;
+
 #[derive(Clone, Debug)]
 pub struct Token {
     pub value: TokenValue,
@@ -26,14 +29,16 @@ impl FileLoc {
 pub enum TokenValue {
     NewLine,
     Comma,
+    Plus,
+    Minus,
     Comment(String),
     NumberLiteral(NumberLiteralTokenValue),
     StringLiteral(String),
     Directive(DirectiveTokenValue),
     Opcode(OpcodeTokenValue),
-    TrapRoutine(TrapRoutineTokenValue),
     Register(RegisterTokenValue),
     Label(String), // 1-20 characters, starting with letter, allows underscores?
+    Error(String),
 }
 
 #[derive(Clone, Debug)]
@@ -42,10 +47,36 @@ pub struct NumberLiteralTokenValue {
     pub value: String,
 }
 
+impl NumberLiteralTokenValue {
+    pub fn to_isize(&self) -> Result<isize, ParseIntError> {
+        match self.format {
+            NumberLiteralFormat::Hex => isize::from_str_radix(&self.value, 16),
+            NumberLiteralFormat::Binary => isize::from_str_radix(&self.value, 2),
+            NumberLiteralFormat::Decimal => self.value.parse(),
+            NumberLiteralFormat::Character => {
+                Ok(self.value.chars().next().unwrap_or_default() as isize)
+            }
+        }
+    }
+}
+
+impl fmt::Display for NumberLiteralTokenValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.format {
+            NumberLiteralFormat::Hex => write!(f, "x{}", self.value),
+            NumberLiteralFormat::Binary => write!(f, "b{}", self.value),
+            NumberLiteralFormat::Decimal => write!(f, "#{}", self.value),
+            NumberLiteralFormat::Character => write!(f, "'{}'", self.value.escape_default()),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum NumberLiteralFormat {
     Hex,
+    Binary,
     Decimal,
+    Character,
 }
 
 #[derive(Clone, Debug)]
@@ -65,22 +96,18 @@ pub enum OpcodeTokenValue {
     BR { n: bool, z: bool, p: bool },
     JMP,
     JSR,
-    // JSRR,
+    JSRR,
     LD,
     LDI,
     LDR,
     LEA,
     NOT,
     RET,
-    // RTI,
+    RTI,
     ST,
     STI,
     STR,
     TRAP,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum TrapRoutineTokenValue {
     GETC,
     OUT,
     PUTS,
@@ -0,0 +1,284 @@
+//! Execution traces written as JSON Lines, and comparison of a trace against a reference.
+//!
+//! Each line records one executed instruction: its `step` number, `pc`, `word`, `instruction`
+//! disassembly, the `line` and `source` text it was assembled from, and the `registers` and
+//! memory `writes` it changed, with words written in hex like `x3000`.
+
+use std::cmp::Ordering;
+use std::io::Write;
+
+use serde_json::{json, Map, Value};
+
+use crate::assembler::Program;
+use crate::ast::Node;
+use crate::console::Console;
+use crate::disassembler::disassemble;
+use crate::simulator::{Machine, SimulatorError};
+
+/// Fields that must match for two steps to be considered the same. Addresses and instructions
+/// are not compared, so programs that are laid out or written differently still line up as long
+/// as they change the same registers and memory.
+const COMPARED_FIELDS: [&str; 2] = ["registers", "writes"];
+
+pub struct Tracer<'a, W: Write> {
+    program: &'a Program,
+    nodes: &'a [Node],
+    lines: Vec<&'a str>,
+    output: W,
+    steps: u64,
+}
+
+impl<'a, W: Write> Tracer<'a, W> {
+    pub fn new(source: &'a str, program: &'a Program, nodes: &'a [Node], output: W) -> Self {
+        Tracer {
+            program,
+            nodes,
+            lines: source.split('\n').collect(),
+            output,
+            steps: 0,
+        }
+    }
+
+    /// Runs like `Machine::run`, writing a record for every instruction executed.
+    pub fn run(
+        &mut self,
+        machine: &mut Machine,
+        console: &mut dyn Console,
+        max_steps: u64,
+    ) -> Result<u64, SimulatorError> {
+        let mut steps = 0;
+        while !machine.halted && steps < max_steps {
+            self.step(machine, console)?;
+            steps += 1;
+        }
+
+        let _ = self.output.flush();
+        Ok(steps)
+    }
+
+    pub fn step(
+        &mut self,
+        machine: &mut Machine,
+        console: &mut dyn Console,
+    ) -> Result<(), SimulatorError> {
+        let address = machine.pc;
+        let word = machine.read_memory(address);
+        let registers = machine.registers;
+        let psr = machine.psr;
+
+        machine.step(console)?;
+        self.steps += 1;
+
+        let mut changed = Map::new();
+        for (i, (&before, &after)) in registers.iter().zip(&machine.registers).enumerate() {
+            if before != after {
+                changed.insert(format!("R{}", i), hex(after));
+            }
+        }
+        if psr != machine.psr {
+            changed.insert("PSR".to_owned(), hex(machine.psr));
+        }
+
+        let writes = machine
+            .writes
+            .iter()
+            .map(|write| (format!("x{:04X}", write.address), hex(write.value)))
+            .collect::<Map<String, Value>>();
+
+        let node = self
+            .program
+            .source_map
+            .get(&address)
+            .map(|&i| &self.nodes[i]);
+        let line = node.map(|node| node.start_loc.line);
+        let record = json!({
+            "step": self.steps,
+            "pc": hex(address),
+            "word": hex(word),
+            "instruction": disassemble(word, address),
+            "line": line,
+            "source": line.map(|line| self.lines[line - 1].trim()),
+            "registers": changed,
+            "writes": writes,
+        });
+        let _ = writeln!(self.output, "{}", record);
+
+        Ok(())
+    }
+}
+
+fn hex(value: u16) -> Value {
+    json!(format!("x{:04X}", value))
+}
+
+/// Compares a trace against a reference trace, returning a report of the first step where they
+/// diverge, or `None` if they match.
+pub fn diff(trace: &str, reference: &str) -> Result<Option<String>, String> {
+    let trace = parse(trace, "trace")?;
+    let reference = parse(reference, "reference trace")?;
+
+    for (step, (record, expected)) in trace.iter().zip(&reference).enumerate() {
+        let Some(difference) = describe_difference(record, expected) else {
+            continue;
+        };
+
+        return Ok(Some(format!(
+            "Traces diverge at step {}: {}\n  trace      {}\n  reference  {}",
+            step + 1,
+            difference,
+            describe(record),
+            describe(expected)
+        )));
+    }
+
+    let report = match trace.len().cmp(&reference.len()) {
+        Ordering::Less => Some(format!(
+            "Trace ends after {} steps, but the reference continues with\n  reference  {}",
+            trace.len(),
+            describe(&reference[trace.len()])
+        )),
+        Ordering::Greater => Some(format!(
+            "Reference ends after {} steps, but the trace continues with\n  trace      {}",
+            reference.len(),
+            describe(&trace[reference.len()])
+        )),
+        Ordering::Equal => None,
+    };
+
+    Ok(report)
+}
+
+fn parse(text: &str, name: &str) -> Result<Vec<Value>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|error| format!("Line {} of the {} is invalid: {}", i + 1, name, error))
+        })
+        .collect()
+}
+
+fn describe_difference(record: &Value, expected: &Value) -> Option<String> {
+    let field = COMPARED_FIELDS
+        .into_iter()
+        .find(|&field| record[field] != expected[field])?;
+
+    let (changes, expected_changes) = (&record[field], &expected[field]);
+    let mut names = changes
+        .as_object()
+        .into_iter()
+        .chain(expected_changes.as_object())
+        .flat_map(|changes| changes.keys())
+        .collect::<Vec<&String>>();
+    names.sort();
+    names.dedup();
+
+    let description = names
+        .into_iter()
+        .filter(|&name| changes[name] != expected_changes[name])
+        .map(|name| {
+            let value = |value: &Value| value.as_str().unwrap_or("unchanged").to_owned();
+            format!(
+                "{} is {} but should be {}",
+                name,
+                value(&changes[name]),
+                value(&expected_changes[name])
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    Some(description)
+}
+
+/// Formats a record as e.g. `x3005 line 12: ADD R1, R1, #1`.
+fn describe(record: &Value) -> String {
+    let pc = text(&record["pc"]);
+    match (record["line"].as_u64(), record["source"].as_str()) {
+        (Some(line), Some(source)) => format!("{} line {}: {}", pc, line, source),
+        _ => format!("{}: {}", pc, text(&record["instruction"])),
+    }
+}
+
+fn text(value: &Value) -> &str {
+    value.as_str().unwrap_or("?")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::console::BufferConsole;
+
+    fn trace(source: &str) -> String {
+        let (program, nodes) = assembler::assemble_source(source).unwrap();
+        let mut machine = Machine::new();
+        machine.load(&program);
+        let mut output = Vec::new();
+        Tracer::new(source, &program, &nodes, &mut output)
+            .run(&mut machine, &mut BufferConsole::new(""), 100)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn records(trace: &str) -> Vec<Value> {
+        parse(trace, "trace").unwrap()
+    }
+
+    #[test]
+    fn records_the_word_written_to_device_registers() {
+        let trace = trace(
+            ".ORIG x3000\nLD R0, CHAR\nSTI R0, DDR\nHALT\nCHAR .FILL x41\nDDR .FILL xFE06\n.END",
+        );
+        let records = records(&trace);
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[0]["registers"],
+            json!({ "R0": "x0041", "PSR": "x0001" })
+        );
+        assert_eq!(records[1]["writes"], json!({ "xFE06": "x0041" }));
+        assert_eq!(records[1]["line"], 3);
+        assert_eq!(records[1]["source"], "STI R0, DDR");
+    }
+
+    #[test]
+    fn matching_traces_have_no_difference() {
+        let source = ".ORIG x3000\nADD R1, R1, #5\nST R1, DATA\nHALT\nDATA .BLKW #1\n.END";
+        // Instructions may differ as long as they change the same registers and memory
+        let other = ".ORIG x3000\nADD R1, R2, #5\nST R1, DATA\nHALT\nDATA .BLKW #1\n.END";
+
+        assert_eq!(diff(&trace(source), &trace(source)), Ok(None));
+        assert_eq!(diff(&trace(other), &trace(source)), Ok(None));
+    }
+
+    #[test]
+    fn reports_the_first_divergent_step() {
+        let reference = trace(".ORIG x3000\nADD R1, R1, #1\nADD R2, R1, #2\nHALT\n.END");
+        let buggy = trace(".ORIG x3000\nADD R1, R1, #1\nADD R2, R1, #3\nHALT\n.END");
+
+        let report = diff(&buggy, &reference).unwrap().unwrap();
+        assert!(report.starts_with("Traces diverge at step 2: R2 is x0004 but should be x0003"));
+        assert!(report.contains("trace      x3001 line 3: ADD R2, R1, #3"));
+        assert!(report.contains("reference  x3001 line 3: ADD R2, R1, #2"));
+    }
+
+    #[test]
+    fn reports_traces_of_different_lengths() {
+        let reference = trace(".ORIG x3000\nADD R1, R1, #1\nADD R1, R1, #1\nHALT\n.END");
+        let short = trace(".ORIG x3000\nADD R1, R1, #1\nHALT\n.END");
+        let short = short.lines().next().unwrap();
+
+        let report = diff(short, &reference).unwrap().unwrap();
+        assert!(report.starts_with("Trace ends after 1 steps"));
+        let report = diff(&reference, short).unwrap().unwrap();
+        assert!(report.starts_with("Reference ends after 1 steps"));
+    }
+
+    #[test]
+    fn reports_invalid_lines() {
+        let error = diff("{}\nnot json", "").unwrap_err();
+        assert!(error.starts_with("Line 2 of the trace is invalid"));
+    }
+}